@@ -1,21 +1,29 @@
-use std::process::Command;
-use std::fs::{DirEntry as StdDirEntry};
-use std::fs::File;
-use std::io::BufReader;
-use std::cmp::Ordering;
+use std::collections::{ BTreeSet, HashMap };
 use std::path::{ Path };
-use std::fmt::{self, Formatter, Display};
-use std::{ fs, io, path::PathBuf, ffi::OsStr };
-
-use crossbeam_channel::{Receiver, Sender};
-
-use ignore::{ Walk, DirEntry };
-use rodio::{Device, Sink};
-use id3::Tag;
-use std::error;
-
-
-use crate::player;
+use std::{ fs, path::PathBuf };
+
+use crossbeam_channel::{ self as channel, Receiver, Sender };
+
+
+use tsiangt::library::{ get_list_of_paths, get_tracks_from_path, is_excluded, is_music_in_folder, is_not_hidden };
+use tsiangt::playlist::{ get_playlist_path, get_session_path, save_m3u, load_m3u };
+use tsiangt::equalizer::{ self, EqSettings, SharedEq, BANDS };
+use tsiangt::loudness::{ self, Progress };
+use tsiangt::tempo::TempoSettings;
+use tsiangt::track::{ Track, sort_by_album, write_tag };
+use tsiangt::output::{ get_output_label, list_outputs };
+use tsiangt::player::OutputEvent;
+use crate::config::{ Config, get_tab_index };
+use crate::theme::Theme;
+use crate::message::{ Level, MessageLog };
+use crate::keys::{ Action, Context, get_action };
+use crate::command::{ self, Command, CommandLine, Seek };
+use crate::ui::{ AppLayout, get_list_height, get_table_height };
+use termion::event::{ Key, MouseButton, MouseEvent };
+use tui::layout::Rect;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicU64, Ordering as AtomicOrdering };
+use std::time::{ Duration, Instant };
 
 
 //#[shell]
@@ -37,32 +45,122 @@ const TABS: [&'static str; 3] = [
     "search"
 ];
 
+/// two clicks on the same row within this time activate it.
+const DOUBLE_CLICK_MS: u64 = 400;
+/// rows moved by one scroll wheel step.
+const WHEEL_STEP: usize = 3;
+
 
 #[derive(Debug, Clone)]
 pub struct ListState<I> {
     pub items: Vec<I>,
-    pub selected: usize
+    pub selected: usize,
+    /// rows toggled with `m`.
+    pub marked: BTreeSet<usize>,
+    /// row where visual mode (`V`) started.
+    pub visual_anchor: Option<usize>,
+    /// first visible row.
+    pub offset: usize
 }
 
 impl<I> ListState<I>{
     fn new(items: Vec<I>) -> ListState<I>{
         ListState {
             items,
-            selected: 0
+            selected: 0,
+            marked: BTreeSet::new(),
+            visual_anchor: None,
+            offset: 0
         } 
     }
 
-    //TODO: make more sense to reverse next with prev. 
-    fn select_prev(&mut self) {
-        if self.selected > 0 {
-            self.selected -= 1
+    /// scrolls so the cursor stays `scroll_off` rows away from the viewport edges,
+    /// as far as the list allows.
+    pub fn update_offset(&mut self, height: usize, scroll_off: usize) {
+        let len = self.items.len();
+        if height == 0 || len == 0 {
+            self.offset = 0;
+            return;
+        }
+        let scroll_off = scroll_off.min((height - 1) / 2);
+
+        if self.selected < self.offset + scroll_off {
+            self.offset = self.selected.saturating_sub(scroll_off);
+        } else if self.selected + scroll_off >= self.offset + height {
+            self.offset = self.selected + scroll_off + 1 - height;
+        }
+        self.offset = self.offset.min(len.saturating_sub(height));
+    }
+
+    /// removes selected rows, returns removed indexes.
+    pub fn remove_selection(&mut self) -> Vec<usize> {
+        let selection = self.get_selection_indexes();
+        for i in selection.iter().rev() {
+            self.items.remove(*i);
+        }
+        self.clear_marks();
+        self.selected = self.selected.min(self.items.len().saturating_sub(1));
+        selection
+    }
+
+    /// moves selected rows one row down (or up), the block stops at list ends.
+    /// returns the new index of every old index.
+    pub fn move_selection(&mut self, down: bool) -> Option<Vec<usize>> {
+        let selection = self.get_selection_indexes();
+        let len = self.items.len();
+        let blocked = match (down, selection.first(), selection.last()) {
+            (true, _, Some(last)) => *last + 1 >= len,
+            (false, Some(first), _) => *first == 0,
+            _ => true
+        };
+        if blocked {
+            return None;
+        }
+
+        // old index of every new position.
+        let mut order: Vec<usize> = (0..len).collect();
+        if down {
+            for i in selection.iter().rev() {
+                order.swap(*i, *i + 1);
+            }
+        } else {
+            for i in selection.iter() {
+                order.swap(*i, *i - 1);
+            }
+        }
+
+        let mut new_index = vec![0; len];
+        for (new, old) in order.iter().enumerate() {
+            new_index[*old] = new;
         }
+
+        let mut items: Vec<Option<I>> = self.items.drain(..).map(Some).collect();
+        self.items = order.iter().map(|old| items[*old].take().unwrap()).collect();
+        self.marked = self.marked.iter().map(|i| new_index[*i]).collect();
+        self.visual_anchor = self.visual_anchor.map(|i| new_index[i]);
+        self.selected = new_index[self.selected];
+        Some(new_index)
     }
 
-    fn select_next( &mut self){
-        if self.selected < ( self.items.len() - 1 ){
-            self.selected += 1
+    fn get_selection_indexes(&self) -> Vec<usize> {
+        let mut selection: BTreeSet<usize> = self.marked.clone();
+        if let Some(anchor) = self.visual_anchor {
+            let (from, to) = if anchor < self.selected { (anchor, self.selected) } else { (self.selected, anchor) };
+            selection.extend(from..=to);
         }
+        if selection.is_empty() && !self.items.is_empty() {
+            selection.insert(self.selected);
+        }
+        selection.into_iter().filter(|i| *i < self.items.len()).collect()
+    }
+
+    pub fn get_selected_items(&self) -> Vec<&I> {
+        self.get_selection_indexes().into_iter().map(|i| &self.items[i]).collect()
+    }
+
+    fn clear_marks(&mut self) {
+        self.marked.clear();
+        self.visual_anchor = None;
     }
 
     fn get_selected_item(&self) -> &I{
@@ -75,6 +173,193 @@ impl<I> ListState<I>{
 
 }
 
+/// Text an item is recognized by, used to jump to items by their first letter.
+pub trait Label {
+    fn get_label(&self) -> String;
+
+    /// text matched by the in-list filter (`/`).
+    fn get_filter_text(&self) -> String {
+        self.get_label()
+    }
+}
+
+impl Label for PathBuf {
+    fn get_label(&self) -> String {
+        self.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+    }
+}
+
+impl Label for Track {
+    fn get_label(&self) -> String {
+        self.title.to_string()
+    }
+
+    fn get_filter_text(&self) -> String {
+        format!("{} {} {}", self.title, self.artist, self.album)
+    }
+}
+
+/// Cursor motions shared by every list (directory, files, playlist, search results).
+pub trait Navigable {
+    fn len(&self) -> usize;
+    fn get_selected(&self) -> usize;
+    fn set_selected(&mut self, i: usize);
+    /// first visible row.
+    fn get_offset(&self) -> usize;
+    fn get_label(&self, i: usize) -> String;
+    fn get_filter_text(&self, i: usize) -> String;
+
+    /// marked rows and rows covered by visual mode, without the cursor row.
+    fn get_marked(&self) -> Vec<usize>;
+    /// rows bulk actions apply to: marked rows, or the cursor row when nothing is marked.
+    fn get_selection(&self) -> Vec<usize>;
+    fn toggle_mark(&mut self);
+    fn toggle_visual(&mut self);
+    fn is_visual(&self) -> bool;
+    fn clear_selection(&mut self);
+
+    /// indexes of items matching `query`, ignoring case.
+    fn find_matches(&self, query: &str) -> Vec<usize> {
+        if query.is_empty() {
+            return vec![];
+        }
+        let query = query.to_lowercase();
+        (0..self.len())
+            .filter(|i| self.get_filter_text(*i).to_lowercase().contains(&query))
+            .collect()
+    }
+
+    /// clamps `i` into the list.
+    fn select(&mut self, i: usize) {
+        let last = self.len().saturating_sub(1);
+        self.set_selected(i.min(last));
+    }
+
+    fn select_next(&mut self, n: usize) {
+        let i = self.get_selected().saturating_add(n);
+        self.select(i);
+    }
+
+    fn select_prev(&mut self, n: usize) {
+        let i = self.get_selected().saturating_sub(n);
+        self.select(i);
+    }
+
+    fn select_first(&mut self) {
+        self.select(0);
+    }
+
+    fn select_last(&mut self) {
+        let last = self.len().saturating_sub(1);
+        self.select(last);
+    }
+
+    /// selects next item (wrapping around) whose label starts with `c`, ignoring case.
+    fn select_starting_with(&mut self, c: char) -> bool {
+        let len = self.len();
+        let c = c.to_lowercase().to_string();
+        for step in 1..=len {
+            let i = (self.get_selected() + step) % len;
+            if self.get_label(i).to_lowercase().starts_with(&c) {
+                self.select(i);
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl<I: Label> Navigable for ListState<I> {
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    fn set_selected(&mut self, i: usize) {
+        self.selected = i;
+    }
+
+    fn get_offset(&self) -> usize {
+        self.offset
+    }
+
+    fn get_label(&self, i: usize) -> String {
+        self.items[i].get_label()
+    }
+
+    fn get_filter_text(&self, i: usize) -> String {
+        self.items[i].get_filter_text()
+    }
+
+    fn get_marked(&self) -> Vec<usize> {
+        if self.marked.is_empty() && self.visual_anchor.is_none() {
+            return vec![];
+        }
+        self.get_selection_indexes()
+    }
+
+    fn get_selection(&self) -> Vec<usize> {
+        self.get_selection_indexes()
+    }
+
+    fn toggle_mark(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        if !self.marked.remove(&self.selected) {
+            self.marked.insert(self.selected);
+        }
+    }
+
+    /// leaving visual mode keeps its rows marked.
+    fn toggle_visual(&mut self) {
+        match self.visual_anchor {
+            Some(_) => {
+                let selection = self.get_selection_indexes();
+                self.marked.extend(selection);
+                self.visual_anchor = None;
+            },
+            None => self.visual_anchor = Some(self.selected)
+        }
+    }
+
+    fn is_visual(&self) -> bool {
+        self.visual_anchor.is_some()
+    }
+
+    fn clear_selection(&mut self) {
+        self.clear_marks();
+    }
+}
+
+/// In-list filter (`/`), matching rows of the current list are highlighted.
+#[derive(Debug, Clone, Default)]
+pub struct ListFilter {
+    pub query: String,
+    pub is_typing: bool,
+    /// cursor position when the filter was opened, incremental matches start from here.
+    origin: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Up,
+    Down,
+    /// `gg` without count goes to the first row, `[count]gg` goes to row `count`.
+    Top,
+    /// `G` without count goes to the last row, `[count]G` goes to row `count`.
+    Bottom,
+    HalfPageUp,
+    HalfPageDown,
+    PageUp,
+    PageDown,
+    Playing,
+    Letter(char),
+}
+
 pub struct PanelState<'a> {
     pub titles: Vec<&'a str>,
     pub index: usize
@@ -126,111 +411,57 @@ impl<'a> TabState<'a>{
     }
 } 
 
-#[derive(Clone, Debug, Eq)]
-pub struct Track {
-    pub file_path: String,
-    pub title: String,
-    pub artist: String,
-    pub album_artist: String,
-    pub album: String,
-    pub year: i32,
-    pub track_num: u32,
-    pub duration: u32,
-}
-
-impl Track {
-
-    pub fn new(path: PathBuf) -> Result<Track, ()> {
-        //NOTE: id3 has problem reading wav file's tag;
-        let tag = Tag::read_from_path(&path);
-        
-        if tag.is_err() {
-            return Err( () );
-        }
-
-        let safe_tag = Tag::read_from_path(&path).unwrap();
-
-        let mut title: String = "".to_string();
-        if let Some(s) = safe_tag.title() {
-            title = s.to_string();
-        }
-
-        let mut artist: String = "".to_string();
-        if let Some(s) = safe_tag.artist() {
-            artist = s.to_string();
-        }
-
-        let mut album: String = "".to_string();
-        if let Some(s) = safe_tag.album() {
-            album = s.to_string();
-        }
-
-        let album_artist;
-        match safe_tag.album_artist() {
-            Some(s) => {
-                album_artist = s.to_string();
-            }
-            None => {
-                album_artist = artist.clone();
-            }
-        }
-
-        let mut year: i32 = 0;
-        if let Some(x) = safe_tag.year() {
-            year = x;
-        }
-
-        let mut track_num: u32 = 0;
-        if let Some(x) = safe_tag.track() {
-            track_num = x;
-        }
-
-        let mut duration: u32 = 0;
-        if let Some(x) = safe_tag.duration() {
-            duration = x;
-        }
 
-        Ok(Track {
-            file_path: path.as_path().to_string_lossy().to_string(),
-            title,
-            artist,
-            album_artist,
-            album,
-            year,
-            track_num,
-            duration,
-        })
-    }
-}
 
-impl PartialOrd for Track {
-    fn partial_cmp(&self, other: &Track) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
 
-impl Ord for Track {
-    fn cmp(&self, other: &Track) -> Ordering {
-        self.track_num.cmp(&other.track_num)
-    }
+/// Sent by the thread of `:analyze`.
+pub enum AnalysisEvent {
+    Progress(Progress),
+    /// songs whose ReplayGain tags couldn't be written, with the reason.
+    NotTagged(Vec<String>),
+    /// measured songs, or why they couldn't be saved.
+    Done(Result<Vec<String>, String>),
 }
 
-impl PartialEq for Track {
-    fn eq(&self, other: &Track) -> bool {
-        self.file_path == other.file_path
-    }
+/// Channels `App` drives the player with, and hears back from it on.
+pub struct AppChannels {
+    pub track_x: Sender<Track>,
+    pub track_p_x: Sender<bool>,
+    pub track_i_rx: Receiver<bool>,
+    pub track_atp_x: Sender<Track>,
+    pub track_s_x: Sender<Duration>,
+    pub track_v_x: Sender<f32>,
+    /// speed and pitch to play at.
+    pub tempo_x: Sender<TempoSettings>,
+    /// songs the player couldn't play, with the reason.
+    pub track_e_rx: Receiver<(Track, String)>,
+    /// outputs to switch the player to, and what came of it.
+    pub output_x: Sender<String>,
+    pub output_rx: Receiver<OutputEvent>,
 }
 
-
-
 pub struct App<'a> {
     pub title: &'a str,
     pub directory: ListState<PathBuf>,
+    /// directory listed in the directory panel.
+    pub current_dir: PathBuf,
+    pub is_tree_view: bool,
+    /// directories expanded in tree view.
+    pub expanded: BTreeSet<PathBuf>,
+    /// cursor position of every visited directory, restored when coming back.
+    cursor_memory: HashMap<PathBuf, usize>,
     pub directory_files: ListState<Track>,
     pub playlist: ListState<Track>,
+    /// up-next queue, consumed before playlist order resumes.
+    pub queue: ListState<Track>,
+    /// song taken from `queue` that is playing, `playing_track_index` is where playlist resumes.
+    pub playing_queued: Option<Track>,
+    pub queue_focus: bool,
     pub tabs: TabState<'a>,
     pub playing_track_index: Option<usize>,
     pub is_quit: bool,
+    /// ctrl-z was pressed, `main` hands the terminal back to the shell.
+    pub is_suspend: bool,
     pub is_playing: bool,
     pub is_playlist_added: bool,
     pub should_select: bool,
@@ -238,28 +469,93 @@ pub struct App<'a> {
     pub track_p_x: Sender<bool>,
     pub track_i_rx: Receiver<bool>,
     pub track_atp_x: Sender<Track>,
+    pub track_s_x: Sender<Duration>,
+    pub track_v_x: Sender<f32>,
+    /// speed and pitch, kept in `config.playback`.
+    pub tempo_x: Sender<TempoSettings>,
+    pub track_position: Arc<AtomicU64>,
+    /// songs the player couldn't play, with the reason.
+    pub track_e_rx: Receiver<(Track, String)>,
+    /// outputs to switch the player to, and what came of it.
+    pub output_x: Sender<String>,
+    pub output_rx: Receiver<OutputEvent>,
+    /// output playing now, an `output::open` spec.
+    pub output: String,
+    pub is_output_picker_active: bool,
+    pub output_choices: Vec<String>,
+    pub output_choice: usize,
+    /// equalizer of the player, changed from the equalizer view and `:eq`.
+    pub equalizer: Arc<SharedEq>,
+    /// preset the bands come from, none once they're adjusted.
+    pub eq_preset: Option<String>,
+    pub is_equalizer_active: bool,
+    pub eq_band: usize,
+    /// playlist saved when tsiangt panics, see `share_session`.
+    pub session: Arc<Mutex<Vec<Track>>>,
+    /// progress of the running loudness analysis.
+    pub analysis_rx: Option<Receiver<AnalysisEvent>>,
+    /// songs failed in a row, playback stops once every song failed.
+    unplayable_in_row: usize,
+    last_track_failed: bool,
     pub is_search_active: bool,
     pub is_track_valid: bool,
-    pub search_input: String
+    pub search_input: String,
+    pub search_results: ListState<Track>,
+    /// every track under the music root, scanned on first search.
+    pub library_tracks: Option<Vec<Track>>,
+    /// rows visible in a list, used by page motions.
+    pub list_height: usize,
+    pub pending_count: Option<usize>,
+    /// first key of a two-key binding (`gg`, `f<letter>`).
+    pub pending_key: Option<Action>,
+    pub list_filter: Option<ListFilter>,
+    pub config: Config,
+    pub theme: Theme,
+    pub is_help_active: bool,
+    pub help_scroll: u16,
+    pub is_command_active: bool,
+    pub command_line: CommandLine,
+    /// recent info, warning and error messages, the newest is shown on the status bar.
+    pub messages: MessageLog,
+    pub is_log_active: bool,
+    pub log_scroll: u16,
+    /// panel, row and time of the last left click, to detect double clicks.
+    last_click: Option<(usize, usize, Instant)>,
+    /// tick events since start, drives marquee scrolling.
+    pub ticks: usize
 }
 
 impl<'a> App<'a> {
     pub fn new(
-        title: &'a str, 
-        track_x :Sender<Track>, 
-        track_p_x: Sender<bool>, 
-        track_i_rx: Receiver<bool>,
-        track_atp_x: Sender<Track>
+        title: &'a str,
+        channels: AppChannels,
+        track_position: Arc<AtomicU64>,
+        equalizer: Arc<SharedEq>,
+        config: Config
         ) -> App<'a> {
+        let AppChannels { track_x, track_p_x, track_i_rx, track_atp_x, track_s_x, track_v_x, tempo_x, track_e_rx, output_x, output_rx } = channels;
+        let mut tabs = TabState::new(TABS.to_vec(),PanelState::new(PANEL.to_vec()));
+        tabs.index = get_tab_index(&config.ui.default_tab).unwrap_or(0);
+        let theme = config.get_theme().unwrap_or_default();
+        let is_tree_view = config.ui.directory_tree;
+
         return 
         App{
             title,
             playlist: ListState::new(Vec::new()),
+            queue: ListState::new(Vec::new()),
+            playing_queued: None,
+            queue_focus: false,
             directory: ListState::new(Vec::new()),
+            current_dir: PathBuf::new(),
+            is_tree_view,
+            expanded: BTreeSet::new(),
+            cursor_memory: HashMap::new(),
             directory_files: ListState::new(Vec::new()),
             playing_track_index: None,
-            tabs: TabState::new(TABS.to_vec(),PanelState::new(PANEL.to_vec())),
+            tabs,
             is_quit: false,
+            is_suspend: false,
             is_playing: false,
             is_playlist_added: false,
             should_select: false,
@@ -267,10 +563,95 @@ impl<'a> App<'a> {
             track_p_x,
             track_i_rx,
             track_atp_x,
+            track_s_x,
+            track_v_x,
+            tempo_x,
+            track_position,
+            track_e_rx,
+            output_x,
+            output_rx,
+            output: config.playback.output.clone(),
+            is_output_picker_active: false,
+            output_choices: Vec::new(),
+            output_choice: 0,
+            equalizer,
+            eq_preset: Some(config.playback.equalizer.clone()),
+            is_equalizer_active: false,
+            eq_band: 0,
+            analysis_rx: None,
+            session: Arc::new(Mutex::new(Vec::new())),
+            unplayable_in_row: 0,
+            last_track_failed: false,
             is_search_active: false,
             is_track_valid: true,
-            search_input: String::new()
+            search_input: String::new(),
+            search_results: ListState::new(Vec::new()),
+            library_tracks: None,
+            list_height: 1,
+            pending_count: None,
+            pending_key: None,
+            list_filter: None,
+            config,
+            theme,
+            is_help_active: false,
+            help_scroll: 0,
+            is_command_active: false,
+            command_line: CommandLine::new(),
+            messages: MessageLog::new(),
+            is_log_active: false,
+            log_scroll: 0,
+            last_click: None,
+            ticks: 0
+        };
+    }
+
+    /// playlist of the last run, if there is one.
+    pub fn restore_session(&mut self){
+        let path = match get_session_path() {
+            Some(p) if p.is_file() => p,
+            _ => return
         };
+        match load_m3u(&path) {
+            Ok(tracks) => self.playlist = ListState::new(tracks),
+            Err(e) => self.warn(format!("last playlist not restored, {}", e))
+        }
+    }
+
+    /// copies the playlist for the panic hook, which can't reach `App`.
+    pub fn share_session(&self){
+        // the panic hook skips a locked session rather than wait for it.
+        if let Ok(mut session) = self.session.try_lock() {
+            if *session != self.playlist.items {
+                *session = self.playlist.items.clone();
+            }
+        }
+    }
+
+    /// stops playback and saves the playlist for next start, tsiangt exits afterwards.
+    pub fn on_exit(&mut self) -> Result<(), failure::Error> {
+        if self.get_playing_track().is_some() {
+            let _ = self.track_p_x.send(false);
+        }
+        match get_session_path() {
+            Some(path) => save_m3u(&path, &self.playlist.items),
+            None => Ok(())
+        }
+    }
+
+    pub fn get_playing_track(&self) -> Option<&Track> {
+        self.playing_queued.as_ref().or_else(|| self.playing_track_index.and_then(|i| self.playlist.items.get(i)))
+    }
+
+    /// playlist row of the playing song, none while a queued song plays.
+    pub fn get_playing_playlist_index(&self) -> Option<usize> {
+        match self.playing_queued {
+            Some(_) => None,
+            None => self.playing_track_index
+        }
+    }
+
+    pub fn is_queue_focused(&self) -> bool {
+        self.queue_focus && !self.queue.items.is_empty()
     }
 
     pub fn set_should_select(&mut self, state: bool){
@@ -281,327 +662,1470 @@ impl<'a> App<'a> {
         self.is_search_active = !self.is_search_active;
     }
 
-    pub fn on_key_up(&mut self){
+    pub fn get_current_list_mut(&mut self) -> Option<&mut dyn Navigable> {
         match self.tabs.get_current_title() {
-           "playlist" => {self.playlist.select_prev()},
-           "library" => { self.handle_panel_select_prev()},
-           _ => {}
+            "playlist" if self.is_queue_focused() => Some(&mut self.queue),
+            "playlist" => Some(&mut self.playlist),
+            "library" => match self.tabs.panels.get_title() {
+                "Directory" => Some(&mut self.directory),
+                "Files" => Some(&mut self.directory_files),
+                _ => None
+            },
+            "search" => Some(&mut self.search_results),
+            _ => None
         }
     }
 
-    pub fn on_key_down(&mut self) {
-         match self.tabs.get_current_title() {
-           "playlist" => {self.playlist.select_next()},
-           "library" => {self.handle_panel_select_next()},
-           _ => {}
+    pub fn get_current_list(&self) -> Option<&dyn Navigable> {
+        match self.tabs.get_current_title() {
+            "playlist" if self.is_queue_focused() => Some(&self.queue),
+            "playlist" => Some(&self.playlist),
+            "library" => match self.tabs.panels.get_title() {
+                "Directory" => Some(&self.directory),
+                "Files" => Some(&self.directory_files),
+                _ => None
+            },
+            "search" => Some(&self.search_results),
+            _ => None
         }
     }
 
-    pub fn handle_panel_select_prev(&mut self){
-        match self.tabs.panels.index {
-            0 => self.directory.select_prev(),
-            1 => self.directory_files.select_prev(),
-            _ => {}
+    /// rows of the current list matching the in-list filter.
+    pub fn get_filter_matches(&self) -> Vec<usize> {
+        match (&self.list_filter, self.get_current_list()) {
+            (Some(filter), Some(list)) => list.find_matches(&filter.query),
+            _ => vec![]
         }
     }
 
+    pub fn on_filter_open(&mut self){
+        let origin = self.get_current_list().map(|l| l.get_selected()).unwrap_or(0);
+        self.list_filter = Some(ListFilter { query: String::new(), is_typing: true, origin });
+    }
 
-    pub fn handle_panel_select_next(&mut self){
-        match self.tabs.panels.index {
-            0 => self.directory.select_next(),
-            1 => self.directory_files.select_next(),
-            _ => {}
+    fn on_filter_input(&mut self, key: Key){
+        let filter = match self.list_filter.as_mut() {
+            Some(f) => f,
+            None => return
+        };
+
+        match key {
+            Key::Char('\n') => {
+                filter.is_typing = false;
+                if filter.query.is_empty() {
+                    self.list_filter = None;
+                }
+                return;
+            },
+            Key::Char(c) => filter.query.push(c),
+            Key::Backspace => { filter.query.pop(); },
+            Key::Esc => {
+                let origin = filter.origin;
+                self.list_filter = None;
+                if let Some(list) = self.get_current_list_mut() {
+                    list.select(origin);
+                }
+                return;
+            },
+            _ => return
+        }
+
+        // incremental: go to first match from where the filter was opened.
+        let origin = filter.origin;
+        let matches = self.get_filter_matches();
+        let next = matches.iter().find(|i| **i >= origin).or_else(|| matches.first()).cloned();
+        if let (Some(i), Some(list)) = (next, self.get_current_list_mut()) {
+            list.select(i);
+        }
+    }
+
+    /// moves to next (or previous when `forward` is false) filter match, wrapping around.
+    pub fn on_filter_match(&mut self, forward: bool, count: Option<usize>){
+        let matches = self.get_filter_matches();
+        if matches.is_empty() {
+            return;
+        }
+
+        for _ in 0..count.unwrap_or(1) {
+            let list = match self.get_current_list_mut() {
+                Some(l) => l,
+                None => return
+            };
+            let selected = list.get_selected();
+            let next = if forward {
+                matches.iter().find(|i| **i > selected).or_else(|| matches.first())
+            } else {
+                matches.iter().rev().find(|i| **i < selected).or_else(|| matches.last())
+            };
+            if let Some(i) = next {
+                list.select(*i);
+            }
+        }
+    }
+
+    /// scrolls every list of the current tab to keep its cursor in view, called before drawing.
+    pub fn update_viewports(&mut self, layout: &AppLayout){
+        let scroll_off = self.config.ui.scroll_off;
+        let panels = &layout.panels;
+        let focused = match self.tabs.get_current_title() {
+            "playlist" => {
+                self.playlist.update_offset(get_table_height(panels[0]), scroll_off);
+                if let Some(area) = panels.get(1) {
+                    self.queue.update_offset(get_table_height(*area), scroll_off);
+                }
+                if self.is_queue_focused() { get_table_height(panels[1]) } else { get_table_height(panels[0]) }
+            },
+            "library" => {
+                self.directory.update_offset(get_list_height(panels[0]), scroll_off);
+                self.directory_files.update_offset(get_table_height(panels[1]), scroll_off);
+                match self.tabs.panels.index {
+                    0 => get_list_height(panels[0]),
+                    _ => get_table_height(panels[1])
+                }
+            },
+            _ => {
+                self.search_results.update_offset(get_table_height(panels[0]), scroll_off);
+                get_table_height(panels[0])
+            }
+        };
+        self.list_height = focused.max(1);
+    }
+
+    pub fn on_tick(&mut self){
+        self.ticks = self.ticks.wrapping_add(1);
+    }
+
+    pub fn get_position(&self) -> Duration {
+        Duration::from_millis(self.track_position.load(AtomicOrdering::Relaxed))
+    }
+
+    pub fn on_motion(&mut self, motion: Motion, count: Option<usize>){
+        let n = count.unwrap_or(1);
+        let half_page = (self.list_height / 2).max(1);
+        let page = self.list_height;
+        // row the playlist plays or resumes from, also while a queued song plays.
+        let playing = self.playing_track_index
+            .and_then(|i| self.playlist.items.get(i))
+            .map(|t| t.file_path.clone());
+        let playing_index = self.get_playing_playlist_index();
+        let tab = self.tabs.get_current_title().to_string();
+        let files = self.directory_files.items.iter().position(|t| Some(&t.file_path) == playing.as_ref());
+
+        let list = match self.get_current_list_mut() {
+            Some(l) => l,
+            None => return
+        };
+
+        match motion {
+            Motion::Up => list.select_prev(n),
+            Motion::Down => list.select_next(n),
+            Motion::Top => match count {
+                Some(line) => list.select(line.saturating_sub(1)),
+                None => list.select_first()
+            },
+            Motion::Bottom => match count {
+                Some(line) => list.select(line.saturating_sub(1)),
+                None => list.select_last()
+            },
+            Motion::HalfPageUp => list.select_prev(half_page * n),
+            Motion::HalfPageDown => list.select_next(half_page * n),
+            Motion::PageUp => list.select_prev(page * n),
+            Motion::PageDown => list.select_next(page * n),
+            Motion::Playing => {
+                let i = match tab.as_str() {
+                    "playlist" => playing_index,
+                    "library" => files,
+                    _ => None
+                };
+                if let Some(i) = i {
+                    list.select(i);
+                }
+            },
+            Motion::Letter(c) => { list.select_starting_with(c); },
         }
     }
 
     pub fn get_playing_track_index(&self) -> Option<usize> {
         match self.tabs.index{
+            0 if self.is_queue_focused() => { Some( self.queue.selected )},
             0 => { Some( self.playlist.selected )},
             1 => { Some( self.directory_files.selected)},
+            2 => { Some( self.search_results.selected)},
             _ => None
         }
     }
 
      pub fn on_select_playing(&mut self) {
         self.is_playing = true;
+        self.playing_queued = None;
         self.playing_track_index = self.get_playing_track_index();
         let track = self.playlist.get_selected_item().clone();
         self.track_x.send(track).unwrap();
     }
 
      pub fn get_next_playing_index(&self) -> Option<usize>{
-        Some(self.playing_track_index.unwrap() + 1)
+        self.playing_track_index.map(|i| i + 1)
      }
 
     pub fn set_next_queue_playing_index(&mut self){
-        self.playing_track_index = Some(self.playing_track_index.unwrap() + 1);
+        if let Some(i) = self.get_next_playing_index() {
+            self.playing_track_index = Some(i % self.playlist.items.len());
+        }
     }
 
     pub fn reset_playing_track_index(&mut self){
         self.playing_track_index = None;
     }
 
-     pub fn on_select_directory_files_playing(&mut self){
-        self.is_playlist_added = true;
-        self.playlist.items.push(self.directory_files.get_selected_item().clone());
-     }
-
-     pub fn on_select_directory(&mut self){
-        self.handle_get_directory();
-     }
-
-     pub fn on_set_directory_files(&mut self){
-        self.handle_get_directory_files(); 
-     }
-
-
-    pub fn set_init_directory(&mut self, p: ListState<PathBuf>){
-        self.directory = p
-    }
+    /// called when the playing song ends, queued songs come first, then playlist order resumes.
+    pub fn on_track_end(&mut self){
+        if !self.last_track_failed {
+            self.unplayable_in_row = 0;
+        }
+        self.last_track_failed = false;
 
-    pub fn set_init_directory_files(&mut self, f: ListState<Track>){
-        self.directory_files = f
-    }
+        if !self.is_playing {
+            return;
+        }
 
-     // not such an elegant way to handle the issue, but it's working.
-     pub fn get_current_item_lists(&mut self) -> usize{
-        let mut size: usize = 0;
-        let tab = self.tabs.get_current_title();
-        if tab == "library"{
-            match self.tabs.panels.get_title(){
-                "Directory" => { size = self.directory.items.len()},
-                "Files" => { size = self.directory_files.items.len()},
-                _ => {}
-            } 
-        } else if tab == "playlist" {
-           size = self.playlist.items.len();
-        } 
-        size
-     }
+        if !self.config.playback.autoplay {
+            self.stop_at_end();
+            return;
+        }
 
+        if !self.queue.items.is_empty() {
+            self.queue.clear_marks();
+            let track = self.queue.items.remove(0);
+            self.queue.selected = self.queue.selected.saturating_sub(1);
+            self.track_atp_x.send(track.clone()).unwrap();
+            self.playing_queued = Some(track);
+            return;
+        }
+        self.playing_queued = None;
 
-    pub fn on_key(&mut self, c: char){
-        if c.is_digit(10) { 
-            if c.to_digit(10).unwrap() as usize <= self.tabs.titles.len(){
-                self.handle_tab(c.to_digit(10).unwrap() as usize) ;
-                self.reset_is_playlist_added();
+        let next = match self.get_next_playing_index() {
+            Some(n) => n,
+            None => {
+                self.stop_at_end();
+                return;
             }
-        } else if c == 'q' { 
-            self.is_quit = true;
-        } else {
+        };
 
-            // check if current panel has any item, 
-            // otherwise disable keypress 
-            // (only tab selection available).
-            if self.get_current_item_lists() > 0 || self.tabs.get_current_title() == "search"{
-               match c {
-                 '\n' => match self.tabs.get_current_title() {
-                     "playlist" => self.on_select_playing(),
-                     "library" => {
-                         match self.tabs.panels.get_title(){
-                             "Directory" => {
-                                 self.on_select_directory();
-                             },
-                             "Files" => { 
-                                 if self.is_track_valid {
-                                    self.set_should_select(true);
-                                    self.on_select_directory_files_playing()
-                                 }
-                             },
-                             _ => {}
-                         }
-                     },
-                     "search" => { 
-                        self.set_is_search_active();
-                     },
-                     _ => {  }
-                 },
-                 'b' => match self.tabs.panels.get_title() { 
-                   "Directory" => self.redirect_parent_path(),
-                   _ => {}
-                 },
-                 ' ' => { self.toggle_is_playing(); self.track_p_x.send(true).unwrap()},
-                 's' => { self.toggle_is_playing(); self.track_p_x.send(false).unwrap()},
-                 'j' => { self.reset_is_playlist_added(); self.on_key_down()},
-                 'k' => { self.reset_is_playlist_added(); self.on_key_up()},
-                 'h' => { self.reset_is_playlist_added(); self.tabs.panels.prev_panel()},
-                 'l' => { self.reset_is_playlist_added(); self.tabs.panels.next_panel()},
-                 _ => {}
-                }   
-            }
+        // handle stop at the end of playlist.
+        let is_last = next >= self.playlist.items.len();
+        if self.playlist.items.is_empty() || (is_last && !self.config.playback.repeat) {
+            self.stop_at_end();
+        } else {
+            let next = next % self.playlist.items.len();
+            self.track_atp_x.send(self.playlist.get_next_selected_item(next).clone()).unwrap();
+            self.set_next_queue_playing_index();
         }
     }
 
-    fn redirect_parent_path(&mut self){
+    /// reports songs the player couldn't play, `on_track_end` then skips to the next one.
+    pub fn on_player_errors(&mut self){
+        while let Ok((_, reason)) = self.track_e_rx.try_recv() {
+            self.warn(format!("skipped, {}", reason));
+            self.unplayable_in_row += 1;
+            self.last_track_failed = true;
+        }
 
-        if let Some(d) = self.directory.get_selected_item().parent(){
-            let d = PathBuf::from(d.parent().unwrap());
+        // with repeat on, skipping would go around forever.
+        if self.is_playing && self.unplayable_in_row > self.playlist.items.len() + self.queue.items.len() {
+            self.unplayable_in_row = 0;
+            self.error("stopped, no playable song left".to_string());
+            self.stop_at_end();
+        }
+    }
 
-           // stop at home root folder.
-           //if d !=  dirs::home_dir().unwrap(){
-               let p = get_list_of_paths(&d);
-               self.set_directory(p.unwrap());
-           //}
-        } 
-        
+    fn stop_at_end(&mut self){
+        self.is_playing = false;
+        self.playing_queued = None;
+        self.track_p_x.send(false).unwrap();
+        self.reset_playing_track_index();
     }
 
+    /// appends selected songs to the up-next queue, `next` puts them in front of it instead.
+    pub fn on_enqueue(&mut self, next: bool){
+        if self.is_queue_focused() {
+            return;
+        }
+        let tracks: Vec<Track> = match self.get_current_track_list_mut() {
+            Some(list) => {
+                let tracks = list.get_selected_items().into_iter().cloned().collect();
+                list.clear_selection();
+                tracks
+            },
+            None => return
+        };
 
-    pub fn handle_tab(&mut self, i: usize){
-        self.tabs.set_tab_index(i);
+        let count = tracks.len();
+        self.queue.clear_marks();
+        if next {
+            self.queue.items.splice(0..0, tracks);
+            self.queue.selected += count;
+        } else {
+            self.queue.items.extend(tracks);
+        }
+        self.queue.selected = self.queue.selected.min(self.queue.items.len().saturating_sub(1));
+        self.info(format!("{} song(s) {}", count, if next { "playing next" } else { "added to queue" }));
     }
 
-    pub fn toggle_is_playing(&mut self){
-        self.is_playing = !self.is_playing;
+    /// plays selected queued song now, taking it out of the queue.
+    pub fn on_play_queued(&mut self){
+        self.queue.clear_marks();
+        let track = self.queue.items.remove(self.queue.selected);
+        self.queue.selected = self.queue.selected.min(self.queue.items.len().saturating_sub(1));
+        self.is_playing = true;
+        self.track_x.send(track.clone()).unwrap();
+        self.playing_queued = Some(track);
     }
 
-    pub fn reset_is_playlist_added(&mut self){
-        self.is_playlist_added = false;
-    }
+     pub fn on_select_directory_files_playing(&mut self){
+        self.is_playlist_added = true;
+        let tracks: Vec<Track> = self.directory_files.get_selected_items().into_iter().cloned().collect();
+        self.playlist.items.extend(tracks);
+        self.directory_files.clear_selection();
+     }
 
-    pub fn handle_get_directory(&mut self){
-            let path = self.directory.get_selected_item();
-            if let Some(res) = get_list_of_paths(&PathBuf::from(path)){
-                if res.iter().any(|item| is_music_in_folder(&item)) {
-                    self.on_set_directory_files();
-                } else {
-                    self.set_directory(res);
+    /// enqueues every song under the selected directory in album order,
+    /// `replace` clears playlist first and starts playing.
+    pub fn on_add_directory(&mut self, replace: bool){
+        let path = self.directory.get_selected_item().clone();
+        let mut tracks = if path.is_dir() {
+            get_tracks_from_path(&path, &self.config.library.exclude)
+        } else {
+            match Track::new(path.clone()) {
+                Ok(t) => vec![t],
+                Err(e) => {
+                    self.error(e.to_string());
+                    return;
                 }
             }
-    }
+        };
 
-    pub fn handle_get_directory_files(&mut self){
-        let path = self.directory.get_selected_item();
-        let files = get_tracks_from_path(&PathBuf::from(path));
-        if files.len() > 0 {
-            self.is_track_valid = true;
-            self.set_directory_files(files); 
+        if tracks.is_empty() {
+            self.warn(format!("no song found in {}", path.display()));
+            return;
+        }
+        sort_by_album(&mut tracks);
+        self.info(format!("{} song(s) added from {}", tracks.len(), path.display()));
+
+        if replace {
+            self.playlist = ListState::new(tracks);
+            self.is_playing = true;
+            self.playing_queued = None;
+            self.playing_track_index = Some(0);
+            self.track_x.send(self.playlist.items[0].clone()).unwrap();
         } else {
-            self.is_track_valid = false; 
+            self.playlist.items.extend(tracks);
         }
+        self.is_playlist_added = true;
     }
 
-    pub fn set_directory(&mut self, lists: Vec<PathBuf>){
-        let mut path_str = vec![];
+    pub fn on_remove_from_playlist(&mut self){
+        let removed = self.playlist.remove_selection();
+        self.playing_track_index = match self.playing_track_index {
+            Some(p) if removed.contains(&p) => None,
+            Some(p) => Some(p - removed.iter().filter(|i| **i < p).count()),
+            None => None
+        };
+    }
+
+    pub fn on_move_in_playlist(&mut self, down: bool){
+        if let Some(new_index) = self.playlist.move_selection(down) {
+            self.playing_track_index = self.playing_track_index.map(|p| new_index[p]);
+        }
+    }
+
+    /// the current list when it holds tracks (files, playlist or search results).
+    pub fn get_current_track_list_mut(&mut self) -> Option<&mut ListState<Track>> {
+        match self.tabs.get_current_title() {
+            "playlist" if self.is_queue_focused() => Some(&mut self.queue),
+            "playlist" => Some(&mut self.playlist),
+            "library" if self.tabs.panels.get_title() == "Files" => Some(&mut self.directory_files),
+            "search" => Some(&mut self.search_results),
+            _ => None
+        }
+    }
 
-        for p in lists {
-            if is_not_hidden(&p) {
-               path_str.push(p);
+    /// writes tag `field` of every selected track, then reloads them in every list.
+    pub fn on_edit_tag(&mut self, field: &str, value: &str){
+        let paths: Vec<String> = match self.get_current_track_list_mut() {
+            Some(list) => list.get_selected_items().into_iter().map(|t| t.file_path.clone()).collect(),
+            None => {
+                self.warn("tags can only be edited in files, playlist or search results".to_string());
+                return;
             }
-            
+        };
+
+        let mut errors = vec![];
+        let mut tagged = vec![];
+        for path in &paths {
+            match write_tag(Path::new(path), field, value) {
+                Ok(_) => tagged.push(path.clone()),
+                Err(e) => errors.push(format!("{}: {}", path, e))
+            }
+        }
+        self.reload_tracks(&tagged);
+        if let Some(list) = self.get_current_track_list_mut() {
+            list.clear_selection();
+        }
+
+        match errors.first() {
+            Some(e) => self.error(format!("{} of {} song(s) not tagged, {}", errors.len(), paths.len(), e)),
+            None => self.info(format!("{} set on {} song(s)", field, paths.len()))
         }
-        self.directory =  ListState::new(path_str);
     }
 
-    pub fn set_directory_files(&mut self, lists: Vec<Track>) {
-        self.directory_files =  ListState::new(lists);
+    /// reads `paths` again, in every list showing them.
+    fn reload_tracks(&mut self, paths: &[String]){
+        for path in paths {
+            if let Ok(track) = Track::new(PathBuf::from(path)) {
+                for list in &mut [&mut self.playlist, &mut self.queue, &mut self.directory_files, &mut self.search_results] {
+                    for item in list.items.iter_mut().filter(|t| &t.file_path == path) {
+                        *item = track.clone();
+                    }
+                }
+            }
+        }
     }
 
-   //  pub fn handle_shell(&self) ->  Result<(), Box<dyn error::Error>> {
-   //     run("/Users/mac/Desktop/test.mid");
-   //     Ok( () )
-   //  }
-   //
-   //pub fn handle_shell(&self){
-       // let your_command = "timidity /Users/gingliu/Desktop/test.mid";
-       // let output = Command::new("bash")
-       // .arg("-c").arg(your_command)
-       // .output().expect("cannot spawn bash")
-       // .stdout;
-       // println!("{}", String::from_utf8(output).expect("Output is not utf-8"));
-        //sh!("timidity /Users/gingliu/Desktop/test.mid");
-   //}
+    /// measures loudness of the selected songs on a thread, see `on_analysis_events`.
+    pub fn on_analyze(&mut self, write_tags: bool){
+        if self.analysis_rx.is_some() {
+            self.warn("an analysis is already running".to_string());
+            return;
+        }
+        let tracks: Vec<Track> = match self.get_current_track_list_mut() {
+            Some(list) => list.get_selected_items().into_iter().cloned().collect(),
+            None => {
+                self.warn("songs can only be analyzed in files, playlist or search results".to_string());
+                return;
+            }
+        };
+        if tracks.is_empty() {
+            self.warn("no song to analyze".to_string());
+            return;
+        }
+        if let Some(list) = self.get_current_track_list_mut() {
+            list.clear_selection();
+        }
 
-}
+        let (analysis_x, analysis_rx) = channel::unbounded();
+        self.analysis_rx = Some(analysis_rx);
+        self.info(format!("analyzing {} song(s)..", tracks.len()));
+        std::thread::spawn(move || {
+            let results = loudness::analyze_tracks(&tracks, |p| { let _ = analysis_x.send(AnalysisEvent::Progress(p)); });
+            let done = loudness::save_results(&results, write_tags)
+                .map(|failed| {
+                    if !failed.is_empty() {
+                        let _ = analysis_x.send(AnalysisEvent::NotTagged(failed));
+                    }
+                    results.into_iter().map(|(t, _, _)| t.file_path).collect()
+                })
+                .map_err(|e| e.to_string());
+            let _ = analysis_x.send(AnalysisEvent::Done(done));
+        });
+    }
 
-pub fn is_not_hidden(entry: &PathBuf) -> bool {
-    entry
-         .file_name()
-         .unwrap()
-         .to_str()
-         .map(|s| !s.starts_with("."))
-         .unwrap_or(false)
-}
+    /// reports measurements, and picks up the new gains once they're saved.
+    pub fn on_analysis_events(&mut self){
+        let events: Vec<AnalysisEvent> = match &self.analysis_rx {
+            Some(rx) => rx.try_iter().collect(),
+            None => return
+        };
+        for event in events {
+            match event {
+                AnalysisEvent::Progress(Progress::Track(track, Ok(m))) =>
+                    self.info(format!("{:.1} LUFS, {:+.2} dB: {}", m.loudness, m.get_gain(), track.title)),
+                AnalysisEvent::Progress(Progress::Track(track, Err(e))) =>
+                    self.warn(format!("not analyzed, {}: {}", track.file_path, e)),
+                AnalysisEvent::Progress(Progress::Album(album, m)) =>
+                    self.info(format!("{:.1} LUFS, {:+.2} dB: album {}", m.loudness, m.get_gain(), album)),
+                AnalysisEvent::NotTagged(failed) =>
+                    self.error(format!("{} song(s) not tagged, {}", failed.len(), failed[0])),
+                AnalysisEvent::Done(Ok(paths)) => {
+                    self.analysis_rx = None;
+                    self.reload_tracks(&paths);
+                    self.info(format!("loudness of {} song(s) saved", paths.len()));
+                },
+                AnalysisEvent::Done(Err(e)) => {
+                    self.analysis_rx = None;
+                    self.error(format!("analysis not saved, {}", e));
+                }
+            }
+        }
+    }
 
-fn is_music_in_folder(path: &PathBuf) -> bool {
+     pub fn on_select_directory(&mut self){
+        self.handle_get_directory();
+     }
 
-    let mut has_audio_file: bool = false;
+     pub fn on_set_directory_files(&mut self){
+        self.handle_get_directory_files(); 
+     }
 
-             if let Some(extension) = path.extension() {
-                 has_audio_file = match extension.to_str() {
-                     Some("mp3") =>  true,
-                     Some("flac") => true,
-                     Some("ogg") => true,
-                     Some("wav") => true,
-                     Some("m4a") => true,
-                     _ => return false,
-                 };
-             } 
 
-    has_audio_file
+    pub fn set_init_directory_files(&mut self, f: ListState<Track>){
+        self.directory_files = f
+    }
 
-}
+     // not such an elegant way to handle the issue, but it's working.
+     pub fn get_current_item_lists(&mut self) -> usize{
+        let mut size: usize = 0;
+        let tab = self.tabs.get_current_title();
+        if tab == "library"{
+            match self.tabs.panels.get_title(){
+                "Directory" => { size = self.directory.items.len()},
+                "Files" => { size = self.directory_files.items.len()},
+                _ => {}
+            } 
+        } else if tab == "playlist" && self.is_queue_focused() {
+           size = self.queue.items.len();
+        } else if tab == "playlist" {
+           size = self.playlist.items.len();
+        } else if tab == "search" {
+           size = self.search_results.items.len();
+        }
+        size
+     }
+
+
+    pub fn on_input(&mut self, key: Key){
+        if self.is_command_active {
+            self.on_command_input(key);
+            return;
+        }
 
-fn is_music(entry: &DirEntry) -> bool {
-    let metadata = fs::metadata(entry.path()).unwrap();
-    if metadata.is_dir() {
-        return false;
+        if self.is_search_active {
+            self.on_search_input(key);
+            return;
+        }
+
+        if self.list_filter.as_ref().map(|f| f.is_typing).unwrap_or(false) {
+            self.on_filter_input(key);
+            return;
+        }
+
+        // esc clears marks and filter before it quits.
+        if key == Key::Esc {
+            if let Some(list) = self.get_current_track_list_mut() {
+                if !list.get_marked().is_empty() {
+                    list.clear_selection();
+                    return;
+                }
+            }
+            if self.list_filter.is_some() {
+                self.list_filter = None;
+                return;
+            }
+        }
+
+        if self.is_help_active {
+            match key {
+                Key::Char('j') | Key::Down => self.help_scroll += 1,
+                Key::Char('k') | Key::Up => self.help_scroll = self.help_scroll.saturating_sub(1),
+                _ => self.toggle_help()
+            }
+            return;
+        }
+
+        if self.is_output_picker_active {
+            self.on_output_picker_input(key);
+            return;
+        }
+
+        if self.is_equalizer_active {
+            self.on_equalizer_input(key);
+            return;
+        }
+
+        if self.is_log_active {
+            match key {
+                Key::Char('j') | Key::Down => self.log_scroll += 1,
+                Key::Char('k') | Key::Up => self.log_scroll = self.log_scroll.saturating_sub(1),
+                _ => self.toggle_log()
+            }
+            return;
+        }
+
+        if let Some(pending) = self.pending_key.take() {
+            let count = self.pending_count.take();
+            match (pending, key) {
+                (Action::Top, Key::Char('g')) => self.on_motion(Motion::Top, count),
+                (Action::JumpToLetter, Key::Char(c)) => self.on_motion(Motion::Letter(c), None),
+                (Action::JumpToBookmark, Key::Char(c)) => self.on_jump_to_bookmark(c),
+                _ => {}
+            }
+            return;
+        }
+
+        // count prefix, `0` only continues a count.
+        if let Key::Char(c) = key {
+            if let Some(d) = c.to_digit(10) {
+                if d > 0 || self.pending_count.is_some() {
+                    let count = self.pending_count.unwrap_or(0).saturating_mul(10).saturating_add(d as usize);
+                    self.pending_count = Some(count);
+                    return;
+                }
+            }
+        }
+
+        let context = Context::from_tab(self.tabs.get_current_title());
+        if let Some(action) = get_action(context, key) {
+            self.on_action(action);
+        }
+
+        // keep count for the second key of `[count]gg`.
+        if self.pending_key.is_none() {
+            self.pending_count = None;
+        }
+    }
+
+    pub fn on_mouse(&mut self, event: MouseEvent, layout: &AppLayout){
+        // termion coordinates are one-based.
+        let (button, x, y) = match event {
+            MouseEvent::Press(b, x, y) => (b, x.saturating_sub(1), y.saturating_sub(1)),
+            _ => return
+        };
+
+        if self.is_command_active || self.is_search_active {
+            return;
+        }
+        self.pending_count = None;
+        self.pending_key = None;
+
+        if self.is_help_active {
+            match button {
+                MouseButton::WheelDown => self.help_scroll += 1,
+                MouseButton::WheelUp => self.help_scroll = self.help_scroll.saturating_sub(1),
+                _ => self.toggle_help()
+            }
+            return;
+        }
+
+        if self.is_output_picker_active {
+            match button {
+                MouseButton::WheelDown => self.move_output_choice(1),
+                MouseButton::WheelUp => self.move_output_choice(-1),
+                _ => self.toggle_output_picker()
+            }
+            return;
+        }
+
+        if self.is_equalizer_active {
+            match button {
+                MouseButton::WheelDown => self.eq_band = (self.eq_band + 1).min(BANDS.len() - 1),
+                MouseButton::WheelUp => self.eq_band = self.eq_band.saturating_sub(1),
+                _ => self.toggle_equalizer()
+            }
+            return;
+        }
+
+        if self.is_log_active {
+            match button {
+                MouseButton::WheelDown => self.log_scroll += 1,
+                MouseButton::WheelUp => self.log_scroll = self.log_scroll.saturating_sub(1),
+                _ => self.toggle_log()
+            }
+            return;
+        }
+
+        if button == MouseButton::Left && is_inside(layout.tabs, x, y) {
+            if let Some(i) = get_tab_at(&self.tabs.titles, layout.tabs, x) {
+                self.handle_tab(i + 1);
+                self.reset_is_playlist_added();
+            }
+            return;
+        }
+
+        if button == MouseButton::Left && is_inside(layout.search_input, x, y) {
+            self.handle_tab(3);
+            self.is_search_active = true;
+            return;
+        }
+
+        if button == MouseButton::Left && is_inside(layout.progress, x, y) {
+            if let Some(t) = self.get_playing_track() {
+                let offset = u64::from(x - layout.progress.x);
+                let target = u64::from(t.duration) * offset / u64::from(layout.progress.width.max(1));
+                self.track_s_x.send(Duration::from_millis(target)).unwrap();
+            }
+            return;
+        }
+
+        let panel = match layout.panels.iter().position(|p| is_inside(*p, x, y)) {
+            Some(p) => p,
+            None => return
+        };
+        self.focus_panel(panel);
+
+        match button {
+            MouseButton::WheelUp => self.on_motion(Motion::Up, Some(WHEEL_STEP)),
+            MouseButton::WheelDown => self.on_motion(Motion::Down, Some(WHEEL_STEP)),
+            MouseButton::Left => {
+                let area = layout.panels[panel];
+                // directory list has no header, tables have a header and a gap below it.
+                let is_table = !(self.tabs.get_current_title() == "library" && panel == 0);
+                let first_row = area.y + 1 + if is_table { 2 } else { 0 };
+                let height = if is_table { get_table_height(area) } else { get_list_height(area) };
+                if y < first_row || (y - first_row) as usize >= height {
+                    return;
+                }
+
+                let row = match self.get_current_list_mut() {
+                    Some(list) => {
+                        let row = list.get_offset() + (y - first_row) as usize;
+                        if row >= list.len() {
+                            return;
+                        }
+                        list.select(row);
+                        row
+                    },
+                    None => return
+                };
+
+                let now = Instant::now();
+                let is_double = match self.last_click {
+                    Some((p, r, t)) => p == panel && r == row && now.duration_since(t) < Duration::from_millis(DOUBLE_CLICK_MS),
+                    None => false
+                };
+                if is_double {
+                    self.last_click = None;
+                    self.on_activate();
+                } else {
+                    self.last_click = Some((panel, row, now));
+                }
+            },
+            _ => {}
+        }
+    }
+
+    fn focus_panel(&mut self, panel: usize){
+        match self.tabs.get_current_title() {
+            "playlist" => self.queue_focus = panel == 1,
+            "library" if panel == 0 => { self.reset_is_playlist_added(); self.tabs.panels.prev_panel() },
+            "library" if self.is_track_valid => { self.reset_is_playlist_added(); self.tabs.panels.next_panel() },
+            _ => {}
+        }
     }
 
-    if let Some(extension) = entry.path().extension() {
-        match extension.to_str() {
-            Some("mp3") => return true,
-            Some("flac") => return true,
-            Some("ogg") => return true,
-            Some("wav") => return true,
-            Some("m4a") => return true,
-            _ => return false,
+    /// what `enter` does on the selected row: play, open directory or add songs.
+    fn on_activate(&mut self){
+        let action = match self.tabs.get_current_title() {
+            "playlist" => Action::Play,
+            "library" => Action::Select,
+            _ => Action::AddToPlaylist
         };
-    } else {
-        return false;
+        self.on_action(action);
     }
-}
 
-pub fn init_tracks(path: &PathBuf) -> ListState<Track>{
-    ListState::new(get_tracks_from_path(path).to_vec())
-}
+    fn on_search_input(&mut self, key: Key){
+        match key {
+            Key::Char('\n') => {
+                self.is_search_active = false;
+                self.run_search();
+            },
+            Key::Char(c) => self.search_input.push(c),
+            Key::Backspace => { self.search_input.pop(); },
+            Key::Esc => { self.is_search_active = false },
+            _ => {}
+        }
+    }
+
+    /// matches title, artist and album of every track under the music root, ignoring case.
+    pub fn run_search(&mut self){
+        let query = self.search_input.trim().to_lowercase();
+        if query.is_empty() {
+            self.search_results = ListState::new(Vec::new());
+            return;
+        }
+
+        if self.library_tracks.is_none() {
+            let tracks = self.config.get_music_dirs()
+                .into_iter()
+                .flat_map(|d| get_tracks_from_path(d, &self.config.library.exclude))
+                .collect();
+            self.library_tracks = Some(tracks);
+        }
 
+        let results = self.library_tracks
+            .iter()
+            .flatten()
+            .filter(|t| {
+                t.title.to_lowercase().contains(&query)
+                || t.artist.to_lowercase().contains(&query)
+                || t.album.to_lowercase().contains(&query)
+            })
+            .cloned()
+            .collect();
+        self.search_results = ListState::new(results);
+    }
 
-pub fn init_directory(path: &PathBuf) -> ListState<PathBuf>{
-        let lists = get_list_of_paths(&path);
-        let mut path_str = vec![];
+    pub fn on_action(&mut self, action: Action){
+        match action {
+            Action::Quit => { self.is_quit = true },
+            Action::Suspend => { self.is_suspend = true },
+            Action::Help => { self.toggle_help() },
+            Action::MessageLog => { self.toggle_log() },
+            Action::OutputPicker => { self.toggle_output_picker() },
+            Action::Equalizer => { self.toggle_equalizer() },
+            Action::SpeedUp => { self.change_tempo(0.1, 0.0) },
+            Action::SpeedDown => { self.change_tempo(-0.1, 0.0) },
+            Action::PitchUp => { self.change_tempo(0.0, 1.0) },
+            Action::PitchDown => { self.change_tempo(0.0, -1.0) },
+            Action::CommandLine => { self.is_command_active = true },
+            Action::TabPlaylist => { self.handle_tab(1); self.reset_is_playlist_added() },
+            Action::TabLibrary => { self.handle_tab(2); self.reset_is_playlist_added() },
+            Action::TabSearch => { self.handle_tab(3); self.reset_is_playlist_added() },
+            Action::JumpToBookmark => { self.pending_key = Some(action) },
+            Action::NextTab => { 
+                let next = (self.tabs.index + 1) % self.tabs.titles.len();
+                self.handle_tab(next + 1); 
+                self.reset_is_playlist_added() 
+            },
+            _ => {
+
+                // check if current panel has any item, 
+                // otherwise disable keypress 
+                // (only tab selection available).
+                if self.get_current_item_lists() == 0 && self.tabs.get_current_title() != "search"{
+                    return;
+                }
 
-        for p in lists.unwrap() {
-            if is_not_hidden(&p) {
-               path_str.push(p);
+                match action {
+                    Action::Play if self.is_queue_focused() => self.on_play_queued(),
+                    Action::Play => self.on_select_playing(),
+                    Action::Select => match self.tabs.panels.get_title(){
+                        "Directory" => {
+                            self.on_select_directory();
+                        },
+                        "Files" => { 
+                            if self.is_track_valid {
+                               self.set_should_select(true);
+                               self.on_select_directory_files_playing()
+                            }
+                        },
+                        _ => {}
+                    },
+                    Action::EditSearch => { 
+                       self.set_is_search_active();
+                    },
+                    Action::AddDirectory | Action::PlayDirectory => {
+                        if self.tabs.panels.get_title() == "Directory" {
+                            self.on_add_directory(action == Action::PlayDirectory);
+                        }
+                    },
+                    Action::ParentDirectory => match self.tabs.panels.get_title() { 
+                      "Directory" => self.redirect_parent_path(),
+                      _ => {}
+                    },
+                    Action::ToggleTree => self.toggle_tree_view(),
+                    Action::Expand => self.on_expand(true),
+                    Action::Collapse => self.on_expand(false),
+                    Action::TogglePause => { self.toggle_is_playing(); self.track_p_x.send(true).unwrap()},
+                    Action::Stop => { self.toggle_is_playing(); self.track_p_x.send(false).unwrap()},
+                    Action::MoveDown => { self.reset_is_playlist_added(); self.on_motion(Motion::Down, self.pending_count)},
+                    Action::MoveUp => { self.reset_is_playlist_added(); self.on_motion(Motion::Up, self.pending_count)},
+                    Action::Top | Action::JumpToLetter => { self.pending_key = Some(action); return },
+                    Action::Bottom => self.on_motion(Motion::Bottom, self.pending_count),
+                    Action::HalfPageUp => self.on_motion(Motion::HalfPageUp, self.pending_count),
+                    Action::HalfPageDown => self.on_motion(Motion::HalfPageDown, self.pending_count),
+                    Action::PageUp => self.on_motion(Motion::PageUp, self.pending_count),
+                    Action::PageDown => self.on_motion(Motion::PageDown, self.pending_count),
+                    Action::JumpToPlaying => self.on_motion(Motion::Playing, None),
+                    Action::Filter => self.on_filter_open(),
+                    Action::NextMatch => self.on_filter_match(true, self.pending_count),
+                    Action::PrevMatch => self.on_filter_match(false, self.pending_count),
+                    Action::AddToPlaylist => {
+                        let tracks: Vec<Track> = self.search_results.get_selected_items().into_iter().cloned().collect();
+                        self.is_playlist_added = true;
+                        self.playlist.items.extend(tracks);
+                        self.search_results.clear_selection();
+                    },
+                    Action::ToggleMark => {
+                        if let Some(list) = self.get_current_track_list_mut() {
+                            list.toggle_mark();
+                            list.select_next(1);
+                        }
+                    },
+                    Action::VisualMode => {
+                        if let Some(list) = self.get_current_track_list_mut() {
+                            list.toggle_visual();
+                        }
+                    },
+                    Action::AddToQueue => self.on_enqueue(false),
+                    Action::PlayNext => self.on_enqueue(true),
+                    Action::Remove if self.is_queue_focused() => { self.queue.remove_selection(); },
+                    Action::Remove => self.on_remove_from_playlist(),
+                    Action::MoveItemDown if self.is_queue_focused() => { self.queue.move_selection(true); },
+                    Action::MoveItemUp if self.is_queue_focused() => { self.queue.move_selection(false); },
+                    Action::MoveItemDown => self.on_move_in_playlist(true),
+                    Action::MoveItemUp => self.on_move_in_playlist(false),
+                    Action::MoveLeft if self.tabs.get_current_title() == "playlist" => self.queue_focus = false,
+                    Action::MoveRight if self.tabs.get_current_title() == "playlist" => self.queue_focus = true,
+                    Action::MoveLeft => { self.reset_is_playlist_added(); self.tabs.panels.prev_panel()},
+                    Action::MoveRight => {
+                        if self.is_track_valid {
+                            self.reset_is_playlist_added();
+                            self.tabs.panels.next_panel()
+                        }
+                    },
+                    _ => {}
+                }
             }
-            
         }
-        ListState::new(path_str)
-}
+    }
 
+    fn on_command_input(&mut self, key: Key){
+        match key {
+            Key::Char('\n') => {
+                self.is_command_active = false;
+                let input = self.command_line.submit();
+                if !input.is_empty() {
+                    self.run_command(&input);
+                }
+            },
+            Key::Char('\t') => self.command_line.complete(),
+            Key::Char(c) => self.command_line.push_char(c),
+            Key::Backspace => {
+                if self.command_line.input.is_empty() {
+                    self.is_command_active = false;
+                } else {
+                    self.command_line.backspace();
+                }
+            },
+            Key::Up => self.command_line.history_prev(),
+            Key::Down => self.command_line.history_next(),
+            Key::Esc | Key::Ctrl('c') => {
+                self.is_command_active = false;
+                self.command_line.clear();
+            },
+            _ => {}
+        }
+    }
 
+    pub fn run_command(&mut self, input: &str){
+        let command = match command::parse(input) {
+            Ok(c) => c,
+            Err(e) => {
+                self.error(e.to_string());
+                return;
+            }
+        };
 
+        match command {
+            Command::Add(path) => {
+                let tracks = if path.is_dir() {
+                    let mut tracks = get_tracks_from_path(&path, &self.config.library.exclude);
+                    sort_by_album(&mut tracks);
+                    tracks
+                } else {
+                    match Track::new(path.clone()) {
+                        Ok(t) => vec![t],
+                        Err(e) => {
+                            self.error(e.to_string());
+                            return;
+                        }
+                    }
+                };
+                self.info(format!("{} song(s) added from {}", tracks.len(), path.display()));
+                self.playlist.items.extend(tracks);
+            },
+            Command::Save(name) => {
+                match get_playlist_path(&name) {
+                    Some(path) => match save_m3u(&path, &self.playlist.items) {
+                        Ok(()) => self.info(format!("playlist saved to {}", path.display())),
+                        Err(e) => self.error(format!("cannot save playlist: {}", e))
+                    },
+                    None => self.error("no data directory to save playlists in".to_string())
+                }
+            },
+            Command::Load(name) => {
+                match get_playlist_path(&name).map(|p| load_m3u(&p)) {
+                    Some(Ok(tracks)) => {
+                        self.info(format!("{} song(s) loaded from {}", tracks.len(), name));
+                        self.playlist = ListState::new(tracks);
+                        self.reset_playing_track_index();
+                    },
+                    Some(Err(e)) => self.error(e.to_string()),
+                    None => self.error("no data directory to load playlists from".to_string())
+                }
+            },
+            Command::Seek(seek) => {
+                if self.get_playing_track().is_none() {
+                    self.warn("nothing is playing".to_string());
+                    return;
+                }
+                let position = self.get_position();
+                let target = match seek {
+                    Seek::To(d) => d,
+                    Seek::Forward(d) => position + d,
+                    Seek::Backward(d) => position.checked_sub(d).unwrap_or_default()
+                };
+                self.track_s_x.send(target).unwrap();
+            },
+            Command::Volume(v) => {
+                self.config.playback.volume = v;
+                self.track_v_x.send(self.config.get_volume()).unwrap();
+            },
+            Command::Speed(speed) => self.set_tempo(TempoSettings { speed, ..self.config.get_tempo() }),
+            Command::Pitch(pitch) => self.set_tempo(TempoSettings { pitch, ..self.config.get_tempo() }),
+            Command::Sort(column) => self.sort_playlist(&column),
+            Command::Tag(field, value) => self.on_edit_tag(&field, &value),
+            Command::Analyze(write_tags) => self.on_analyze(write_tags),
+            Command::EqPreset(name) => self.load_eq_preset(&name),
+            Command::EqSave(name) => {
+                match equalizer::save_preset(&name, self.equalizer.get().gains) {
+                    Ok(_) => {
+                        self.eq_preset = Some(name.clone());
+                        self.info(format!("equalizer preset \"{}\" saved", name));
+                    },
+                    Err(e) => self.error(format!("preset not saved, {}", e))
+                }
+            },
+            Command::EqBypass => self.toggle_eq_bypass(),
+            Command::Quit => { self.is_quit = true },
+        }
+    }
 
-pub fn get_list_of_paths(root: &PathBuf) -> Option<Vec<PathBuf>> {
-    let mut result = vec![];
+    pub fn sort_playlist(&mut self, column: &str){
+        // row the playlist plays or resumes from, also while a queued song plays.
+        let playing = self.playing_track_index
+            .and_then(|i| self.playlist.items.get(i))
+            .map(|t| t.file_path.clone());
+
+        self.playlist.items.sort_by(|a, b| match column {
+            "year" => a.year.cmp(&b.year),
+            "track" => a.track_num.cmp(&b.track_num),
+            "duration" => a.duration.cmp(&b.duration),
+            _ => a.get_column(column).to_lowercase().cmp(&b.get_column(column).to_lowercase())
+        });
+
+        if let Some(p) = playing {
+            self.playing_track_index = self.playlist.items.iter().position(|t| t.file_path == p);
+        }
+    }
 
-    // validation.
-    let is_empty_folder = fs::read_dir(root).map(|mut i| i.next().is_none()).unwrap_or(false);
-    //let is_contains_no_folder = is_no_folder_inside(root);
+    pub fn toggle_help(&mut self){
+        self.is_help_active = !self.is_help_active;
+        self.help_scroll = 0;
+    }
+
+    /// lists outputs anew each time, devices come and go.
+    pub fn toggle_output_picker(&mut self){
+        self.is_output_picker_active = !self.is_output_picker_active;
+        if !self.is_output_picker_active {
+            return;
+        }
+        self.output_choices = list_outputs();
+        if !self.output_choices.contains(&self.output) {
+            self.output_choices.push(self.output.clone());
+        }
+        self.output_choice = self.output_choices.iter().position(|o| *o == self.output).unwrap_or(0);
+    }
+
+    fn on_output_picker_input(&mut self, key: Key){
+        match key {
+            Key::Char('j') | Key::Down => self.move_output_choice(1),
+            Key::Char('k') | Key::Up => self.move_output_choice(-1),
+            Key::Char('\n') => {
+                let choice = self.output_choices[self.output_choice].clone();
+                self.toggle_output_picker();
+                if choice != self.output {
+                    self.output_x.send(choice).unwrap();
+                }
+            },
+            _ => self.toggle_output_picker()
+        }
+    }
+
+    fn move_output_choice(&mut self, step: isize){
+        let last = self.output_choices.len().saturating_sub(1) as isize;
+        self.output_choice = (self.output_choice as isize + step).clamp(0, last) as usize;
+    }
+
+    /// steps speed and pitch, speed is rounded so steps don't drift.
+    fn change_tempo(&mut self, speed_step: f32, pitch_step: f32){
+        let tempo = self.config.get_tempo();
+        self.set_tempo(TempoSettings {
+            speed: ((tempo.speed + speed_step) * 10.0).round() / 10.0,
+            pitch: tempo.pitch + pitch_step,
+        });
+    }
+
+    fn set_tempo(&mut self, tempo: TempoSettings){
+        let tempo = tempo.clamp();
+        self.config.playback.speed = tempo.speed;
+        self.config.playback.pitch = tempo.pitch;
+        self.tempo_x.send(tempo).unwrap();
+        self.info(format!("speed {}x, pitch {:+} semitone(s)", tempo.speed, tempo.pitch));
+    }
+
+    pub fn toggle_equalizer(&mut self){
+        self.is_equalizer_active = !self.is_equalizer_active;
+    }
+
+    fn on_equalizer_input(&mut self, key: Key){
+        match key {
+            Key::Char('j') | Key::Down => self.eq_band = (self.eq_band + 1).min(BANDS.len() - 1),
+            Key::Char('k') | Key::Up => self.eq_band = self.eq_band.saturating_sub(1),
+            Key::Char('l') | Key::Right => self.adjust_eq_band(1.0),
+            Key::Char('h') | Key::Left => self.adjust_eq_band(-1.0),
+            Key::Char('0') => self.adjust_eq_band(-self.equalizer.get().gains[self.eq_band]),
+            Key::Char('b') => self.toggle_eq_bypass(),
+            Key::Char(']') => self.cycle_eq_preset(1),
+            Key::Char('[') => self.cycle_eq_preset(-1),
+            Key::Char('s') => {
+                self.toggle_equalizer();
+                self.is_command_active = true;
+                self.command_line.input = "eq save ".to_string();
+            },
+            _ => self.toggle_equalizer()
+        }
+    }
+
+    fn set_equalizer<F>(&mut self, change: F)
+        where F: FnOnce(&mut EqSettings)
+    {
+        let mut settings = self.equalizer.get();
+        change(&mut settings);
+        self.equalizer.set(settings);
+    }
+
+    fn adjust_eq_band(&mut self, step: f32){
+        let band = self.eq_band;
+        self.set_equalizer(|s| s.adjust(band, step));
+        self.eq_preset = None;
+    }
+
+    fn toggle_eq_bypass(&mut self){
+        self.set_equalizer(|s| s.bypass = !s.bypass);
+        let state = if self.equalizer.get().bypass { "bypassed" } else { "on" };
+        self.info(format!("equalizer {}", state));
+    }
+
+    fn load_eq_preset(&mut self, name: &str){
+        match equalizer::get_preset(name) {
+            Some(gains) => {
+                self.set_equalizer(|s| s.gains = gains);
+                self.eq_preset = Some(name.to_string());
+            },
+            None => self.error(format!("unknown equalizer preset \"{}\"", name))
+        }
+    }
+
+    /// built-in and saved presets, in `list_presets` order.
+    fn cycle_eq_preset(&mut self, step: isize){
+        let presets = equalizer::list_presets();
+        let current = self.eq_preset.as_ref().and_then(|p| presets.iter().position(|n| n == p));
+        let next = match current {
+            Some(i) => (i as isize + step).rem_euclid(presets.len() as isize) as usize,
+            None => 0
+        };
+        let name = presets[next].clone();
+        self.load_eq_preset(&name);
+    }
+
+    /// reports output switches, and devices that disappeared.
+    pub fn on_output_events(&mut self){
+        while let Ok(event) = self.output_rx.try_recv() {
+            match event {
+                OutputEvent::Switched(spec) => {
+                    self.info(format!("playing on {}", get_output_label(&spec)));
+                    self.output = spec;
+                },
+                OutputEvent::Fallback(name) => {
+                    self.warn(format!("output device not found, playing on {}", name));
+                    self.output = "device".to_string();
+                },
+                OutputEvent::Failed(reason) => self.error(format!("output not changed, {}", reason))
+            }
+        }
+    }
+
+    pub fn toggle_log(&mut self){
+        self.is_log_active = !self.is_log_active;
+        self.log_scroll = 0;
+    }
+
+    pub fn info(&mut self, text: String){
+        self.messages.push(Level::Info, text);
+    }
+
+    pub fn warn(&mut self, text: String){
+        self.messages.push(Level::Warning, text);
+    }
+
+    pub fn error(&mut self, text: String){
+        self.messages.push(Level::Error, text);
+    }
+
+    fn redirect_parent_path(&mut self){
+        let child = self.current_dir.clone();
+        let roots = self.config.get_music_dirs();
+        let parent = match child.parent() {
+            // several roots are entries of a top level above them.
+            Some(_) if roots.len() > 1 && roots.contains(&&child) => PathBuf::new(),
+            Some(p) => p.to_path_buf(),
+            None => return
+        };
+
+        let is_remembered = self.cursor_memory.contains_key(&parent);
+        self.open_directory(parent);
+        // first time here, put cursor on the directory we came from.
+        if !is_remembered {
+            if let Some(i) = self.directory.items.iter().position(|p| *p == child) {
+                self.directory.select(i);
+            }
+        }
+    }
+
+    /// opens the only music root, or the top level listing every root when there are several.
+    pub fn open_library_root(&mut self){
+        let roots = self.config.get_music_dirs();
+        let dir = match roots.as_slice() {
+            [root] => root.to_path_buf(),
+            _ => PathBuf::new()
+        };
+        self.open_directory(dir);
+    }
+
+    pub fn is_library_top(&self) -> bool {
+        self.current_dir.as_os_str().is_empty()
+    }
+
+    pub fn on_jump_to_bookmark(&mut self, key: char){
+        let path = match self.config.get_bookmark(key) {
+            Some(p) => p.clone(),
+            None => {
+                let keys: Vec<&str> = self.config.library.bookmarks.keys().map(String::as_str).collect();
+                self.warn(match keys.is_empty() {
+                    true => "no bookmarks, add some under [library.bookmarks] in config".to_string(),
+                    false => format!("no bookmark '{}', bookmarks: {}", key, keys.join(" "))
+                });
+                return;
+            }
+        };
+        if !path.is_dir() {
+            self.error(format!("bookmark '{}' is not a directory: {}", key, path.display()));
+            return;
+        }
+
+        self.handle_tab(2);
+        self.reset_is_playlist_added();
+        self.tabs.panels.prev_panel();
+        self.open_directory(path);
+    }
+
+    /// lists `dir` in the directory panel, the cursor goes where it was last time.
+    /// an unreadable `dir` is reported and the current listing is kept.
+    pub fn open_directory(&mut self, dir: PathBuf){
+        let rows = match self.get_directory_rows(&dir) {
+            Ok(rows) => rows,
+            Err(e) => {
+                self.error(e.to_string());
+                return;
+            }
+        };
+
+        if !self.directory.items.is_empty() {
+            self.cursor_memory.insert(self.current_dir.clone(), self.directory.selected);
+        }
+        self.current_dir = dir;
+        self.directory = ListState::new(rows);
+        let selected = self.cursor_memory.get(&self.current_dir).cloned().unwrap_or(0);
+        self.directory.select(selected);
+    }
+
+    /// relists current directory, keeping the cursor on the same entry.
+    fn refresh_directory(&mut self){
+        let selected = self.directory.items.get(self.directory.selected).cloned();
+        match self.get_directory_rows(&self.current_dir) {
+            Ok(rows) => self.directory = ListState::new(rows),
+            Err(e) => {
+                self.error(e.to_string());
+                return;
+            }
+        }
+        if let Some(i) = selected.and_then(|s| self.directory.items.iter().position(|p| *p == s)) {
+            self.directory.select(i);
+        }
+    }
+
+    /// entries of `dir`, followed by entries of expanded subdirectories in tree view.
+    /// unreadable subdirectories show no entries.
+    fn get_directory_rows(&self, dir: &PathBuf) -> Result<Vec<PathBuf>, failure::Error> {
+        let entries = if dir.as_os_str().is_empty() {
+            self.config.get_music_dirs().into_iter().cloned().collect()
+        } else {
+            get_list_of_paths(dir)?
+        };
+
+        let mut rows = vec![];
+        for p in entries {
+            if !is_not_hidden(&p) || is_excluded(&p, &self.config.library.exclude) {
+                continue;
+            }
+            let is_expanded = self.is_tree_view && p.is_dir() && self.expanded.contains(&p);
+            rows.push(p.clone());
+            if is_expanded {
+                rows.extend(self.get_directory_rows(&p).unwrap_or_default());
+            }
+        }
+        Ok(rows)
+    }
+
+    /// nesting level of a directory panel row, 0 for entries of current directory.
+    pub fn get_directory_depth(&self, path: &Path) -> usize {
+        if self.is_library_top() {
+            return self.config.get_root_of(path)
+                .and_then(|r| path.strip_prefix(r).ok())
+                .map(|p| p.components().count())
+                .unwrap_or(0);
+        }
+        path.strip_prefix(&self.current_dir)
+            .map(|p| p.components().count().saturating_sub(1))
+            .unwrap_or(0)
+    }
+
+    /// roots listed at the top level show their whole path, other entries their name.
+    pub fn get_directory_label(&self, path: &PathBuf) -> String {
+        match self.is_library_top() && self.config.library.music_dirs.contains(path) {
+            true => path.display().to_string(),
+            false => path.get_label()
+        }
+    }
 
-    if  !is_empty_folder {
-        for path in fs::read_dir(root).unwrap(){
-            let path = path.unwrap().path();
-            result.push(path.to_owned());
+    pub fn toggle_tree_view(&mut self){
+        self.is_tree_view = !self.is_tree_view;
+        // rows of collapsed subdirectories are gone, stay on the closest visible one.
+        let selected = self.directory.items.get(self.directory.selected).cloned();
+        self.refresh_directory();
+        if let Some(mut path) = selected {
+            while !self.directory.items.contains(&path) {
+                match path.parent() {
+                    Some(p) if p.starts_with(&self.current_dir) && p != path.as_path() => path = p.to_path_buf(),
+                    _ => return
+                }
+            }
+            if let Some(i) = self.directory.items.iter().position(|p| *p == path) {
+                self.directory.select(i);
+            }
+        }
+    }
+
+    /// expands selected directory, or collapses it (or the one it is in when it isn't expanded).
+    pub fn on_expand(&mut self, expand: bool){
+        if !self.is_tree_view || self.tabs.panels.get_title() != "Directory" {
+            return;
+        }
+        let path = self.directory.get_selected_item().clone();
+
+        if expand {
+            if path.is_dir() {
+                self.expanded.insert(path);
+            }
+        } else if !self.expanded.remove(&path) {
+            let is_root = self.is_library_top() && self.config.library.music_dirs.contains(&path);
+            let parent = match path.parent() {
+                Some(p) if p != self.current_dir.as_path() && !is_root => p.to_path_buf(),
+                _ => return
+            };
+            self.expanded.remove(&parent);
+            self.refresh_directory();
+            if let Some(i) = self.directory.items.iter().position(|p| *p == parent) {
+                self.directory.select(i);
+            }
+            return;
+        }
+        self.refresh_directory();
+    }
+
+
+    pub fn handle_tab(&mut self, i: usize){
+        self.tabs.set_tab_index(i);
+    }
+
+    pub fn toggle_is_playing(&mut self){
+        self.is_playing = !self.is_playing;
+    }
+
+    pub fn reset_is_playlist_added(&mut self){
+        self.is_playlist_added = false;
+    }
+
+    pub fn handle_get_directory(&mut self){
+            let path = self.directory.get_selected_item().clone();
+            if !path.is_dir() {
+                return;
+            }
+            match get_list_of_paths(&path) {
+                Ok(res) => if res.iter().any(|item| is_music_in_folder(&item)) {
+                    self.on_set_directory_files();
+                } else if !res.is_empty() {
+                    self.open_directory(path);
+                },
+                Err(e) => self.error(e.to_string())
+            }
+    }
+
+    pub fn handle_get_directory_files(&mut self){
+        let path = self.directory.get_selected_item().clone();
+        let files = get_tracks_from_path(&path, &self.config.library.exclude);
+        if files.len() > 0 {
+            self.is_track_valid = true;
+            self.set_directory_files(files); 
+        } else {
+            self.is_track_valid = false; 
+            self.warn(format!("no readable song in {}", path.display()));
+        }
+    }
+
+    pub fn set_directory_files(&mut self, lists: Vec<Track>) {
+        self.directory_files =  ListState::new(lists);
+    }
+
+   //  pub fn handle_shell(&self) ->  Result<(), Box<dyn error::Error>> {
+   //     run("/Users/mac/Desktop/test.mid");
+   //     Ok( () )
+   //  }
+   //
+   //pub fn handle_shell(&self){
+       // let your_command = "timidity /Users/gingliu/Desktop/test.mid";
+       // let output = Command::new("bash")
+       // .arg("-c").arg(your_command)
+       // .output().expect("cannot spawn bash")
+       // .stdout;
+       // println!("{}", String::from_utf8(output).expect("Output is not utf-8"));
+        //sh!("timidity /Users/gingliu/Desktop/test.mid");
+   //}
+
+}
+
+fn is_inside(area: Rect, x: u16, y: u16) -> bool {
+    x >= area.left() && x < area.right() && y >= area.top() && y < area.bottom()
+}
+
+/// index of the tab title at column `x`, following how `tui::widgets::Tabs` lays out
+/// titles: one space of padding on each side, divided by `|`.
+fn get_tab_at(titles: &[&str], area: Rect, x: u16) -> Option<usize> {
+    let mut left = area.x + 1;
+    for (i, title) in titles.iter().enumerate() {
+        let start = left + 1;
+        let end = start + title.chars().count() as u16;
+        if x >= left && x <= end {
+            return Some(i);
         }
-        Some(result)
-    } else {
-        None
+        left = end + 2;
     }
-    
+    None
 }
 
+pub fn init_tracks(path: &PathBuf, exclude: &[String]) -> ListState<Track>{
+    ListState::new(get_tracks_from_path(path, exclude).to_vec())
+}
 
 fn is_no_folder_inside(d: &PathBuf) -> bool {
     let mut is_no_folder: bool = false;
@@ -624,34 +2148,133 @@ fn is_no_folder_inside(d: &PathBuf) -> bool {
 }
 
 
-
-fn get_tracks_from_path(path: &PathBuf) -> Vec<Track>{
-        let mut lists = Vec::new();
-        for result in Walk::new(path) {
-        if let Ok(entry) = result {
-            if is_music(&entry) {
-                let track = Track::new(entry.into_path());
-                //println!("this is valid music {:?}", track);
-                if let Ok(t) = track{
-                   lists.push(t);
-                }
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tsiangt::replaygain::ReplayGain;
+
+    fn get_track(title: &str) -> Track {
+        Track {
+            file_path: format!("/music/{}.mp3", title),
+            title: title.to_string(),
+            artist: String::new(),
+            album_artist: String::new(),
+            album: String::new(),
+            year: 0,
+            track_num: 0,
+            disc_num: 0,
+            duration: 0,
+            replay_gain: ReplayGain::default(),
         }
-	}
+    }
 
+    fn get_app() -> App<'static> {
+        let channels = AppChannels {
+            track_x: channel::unbounded().0,
+            track_p_x: channel::unbounded().0,
+            track_i_rx: channel::unbounded().1,
+            track_atp_x: channel::unbounded().0,
+            track_s_x: channel::unbounded().0,
+            track_v_x: channel::unbounded().0,
+            tempo_x: channel::unbounded().0,
+            track_e_rx: channel::unbounded().1,
+            output_x: channel::unbounded().0,
+            output_rx: channel::unbounded().1,
+        };
+        App::new("/tsiangt/", channels, Arc::new(AtomicU64::new(0)), Arc::new(SharedEq::default()), Config::default())
+    }
 
-        lists
-}
+    /// app on the playlist tab, with `len` songs in the playlist.
+    fn get_app_with_playlist(len: usize) -> App<'static> {
+        let mut app = get_app();
+        app.tabs.index = 0;
+        app.playlist = ListState::new((0..len).map(|i| get_track(&i.to_string())).collect());
+        app
+    }
+
+    /// list of `len` rows with the cursor on `selected`, scrolled for a view of `height` rows.
+    fn get_scrolled(len: usize, selected: usize, offset: usize, height: usize, scroll_off: usize) -> usize {
+        let mut list = ListState::new(vec![0; len]);
+        list.selected = selected;
+        list.offset = offset;
+        list.update_offset(height, scroll_off);
+        list.offset
+    }
 
+    fn press(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.on_input(Key::Char(c));
+        }
+    }
 
+    #[test]
+    fn offset_keeps_scroll_off_rows_around_the_cursor() {
+        // moving down, the cursor stays 3 rows above the bottom.
+        assert_eq!(get_scrolled(100, 6, 0, 10, 3), 0);
+        assert_eq!(get_scrolled(100, 7, 0, 10, 3), 1);
+        // moving up, 3 rows below the top.
+        assert_eq!(get_scrolled(100, 12, 10, 10, 3), 9);
+        // no scrolling while the cursor is in between.
+        assert_eq!(get_scrolled(100, 15, 10, 10, 3), 10);
+    }
 
+    #[test]
+    fn offset_is_clamped_at_list_ends() {
+        assert_eq!(get_scrolled(100, 0, 50, 10, 3), 0);
+        assert_eq!(get_scrolled(100, 2, 50, 10, 3), 0);
+        assert_eq!(get_scrolled(100, 99, 0, 10, 3), 90);
+        assert_eq!(get_scrolled(100, 97, 0, 10, 3), 90);
+        // a list that shrank under the offset.
+        assert_eq!(get_scrolled(20, 19, 50, 10, 3), 10);
+    }
 
-// helper.
-pub fn get_input() -> String{
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
-            Ok(i) => { println!("input = {}", i);},
-            Err(e) => {},
+    #[test]
+    fn short_list_is_not_scrolled() {
+        for selected in 0..3 {
+            assert_eq!(get_scrolled(3, selected, 2, 10, 3), 0);
         }
-        input.trim().to_string()
+        assert_eq!(get_scrolled(0, 0, 5, 10, 3), 0);
+        assert_eq!(get_scrolled(10, 5, 5, 0, 3), 0);
+    }
+
+    #[test]
+    fn scroll_off_beyond_half_the_view_centers_the_cursor() {
+        assert_eq!(get_scrolled(100, 50, 0, 5, 10), 48);
+        assert_eq!(get_scrolled(100, 50, 60, 5, 10), 48);
+        assert_eq!(get_scrolled(100, 50, 0, 4, 10), 48);
+        assert_eq!(get_scrolled(100, 1, 0, 5, 10), 0);
+    }
+
+    #[test]
+    fn count_prefix_moves_that_many_rows() {
+        let mut app = get_app_with_playlist(30);
+        press(&mut app, "10j");
+        assert_eq!(app.playlist.selected, 10);
+        press(&mut app, "3k");
+        assert_eq!(app.playlist.selected, 7);
+        // a lone `0` counts nothing.
+        press(&mut app, "0j");
+        assert_eq!(app.playlist.selected, 8);
+        assert_eq!(app.tabs.index, 0);
+    }
+
+    #[test]
+    fn sort_keeps_the_playlist_resume_point_while_a_queued_song_plays() {
+        let mut app = get_app();
+        app.playlist = ListState::new(vec![get_track("c"), get_track("a"), get_track("b")]);
+        app.playing_track_index = Some(0);
+        app.playing_queued = Some(get_track("queued"));
+        app.sort_playlist("title");
+        assert_eq!(app.playing_track_index, Some(2));
+        assert_eq!(app.playlist.items[2].title, "c");
+    }
+
+    #[test]
+    fn count_prefix_goes_to_row_with_gg() {
+        let mut app = get_app_with_playlist(30);
+        press(&mut app, "G");
+        assert_eq!(app.playlist.selected, 29);
+        press(&mut app, "12gg");
+        assert_eq!(app.playlist.selected, 11);
+    }
 }
@@ -0,0 +1,394 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+
+use failure::Error;
+
+use crate::config::{ COLUMNS, expand_tilde };
+use tsiangt::equalizer::list_presets;
+use tsiangt::playlist::list_playlists;
+use tsiangt::tempo::{ MAX_PITCH, MAX_SPEED, MIN_SPEED };
+
+
+/// name, usage, description.
+pub const COMMANDS: [(&str, &str, &str); 12] = [
+    ("add", ":add <path>", "add a song or every song under a directory to playlist"),
+    ("save", ":save <playlist>", "save playlist"),
+    ("load", ":load <playlist>", "replace playlist with a saved one"),
+    ("seek", ":seek <[+-]m:ss>", "seek to position, or relative with + / -"),
+    ("vol", ":vol <0-100>", "set volume"),
+    ("speed", ":speed <0.5-3>", "set playback speed, pitch stays the same"),
+    ("pitch", ":pitch <-12-12>", "shift pitch by semitones, 0 resets it"),
+    ("sort", ":sort <column>", "sort playlist by column"),
+    ("tag", ":tag <field> <value>", "set tag of selected songs (title, artist, album, album_artist, year, track)"),
+    ("analyze", ":analyze [tags]", "measure loudness of selected songs, tags also writes ReplayGain tags"),
+    ("eq", ":eq <preset|save <name>|bypass>", "load or save an equalizer preset, or toggle bypass"),
+    ("q", ":q", "quit"),
+];
+
+pub const TAG_FIELDS: [&str; 6] = [
+    "title",
+    "artist",
+    "album",
+    "album_artist",
+    "year",
+    "track"
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Add(PathBuf),
+    Save(String),
+    Load(String),
+    Seek(Seek),
+    Volume(u8),
+    Speed(f32),
+    Pitch(f32),
+    Sort(String),
+    Tag(String, String),
+    /// whether to write ReplayGain tags too.
+    Analyze(bool),
+    EqPreset(String),
+    EqSave(String),
+    EqBypass,
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Seek {
+    To(Duration),
+    Forward(Duration),
+    Backward(Duration),
+}
+
+pub fn parse(input: &str) -> Result<Command, Error> {
+    let input = input.trim();
+    let (name, arg) = match input.find(char::is_whitespace) {
+        Some(i) => (&input[..i], input[i..].trim()),
+        None => (input, "")
+    };
+
+    let require_arg = || -> Result<&str, Error> {
+        if arg.is_empty() {
+            let usage = COMMANDS.iter().find(|c| c.0 == name).map(|c| c.1).unwrap_or("");
+            bail!("usage: {}", usage);
+        }
+        Ok(arg)
+    };
+
+    let command = match name {
+        "add" => Command::Add(expand_tilde(Path::new(require_arg()?))),
+        "save" | "w" => Command::Save(require_arg()?.to_string()),
+        "load" | "e" => Command::Load(require_arg()?.to_string()),
+        "seek" => Command::Seek(parse_seek(require_arg()?)?),
+        "vol" | "volume" => {
+            let v: u8 = require_arg()?.parse().map_err(|_| format_err!("invalid volume \"{}\"", arg))?;
+            Command::Volume(v.min(100))
+        },
+        "speed" => {
+            let s: f32 = require_arg()?.trim_end_matches('x').parse().map_err(|_| format_err!("invalid speed \"{}\"", arg))?;
+            if !(MIN_SPEED..=MAX_SPEED).contains(&s) {
+                bail!("speed must be between {} and {}", MIN_SPEED, MAX_SPEED);
+            }
+            Command::Speed(s)
+        },
+        "pitch" => {
+            let p: f32 = require_arg()?.trim_start_matches('+').parse().map_err(|_| format_err!("invalid pitch \"{}\"", arg))?;
+            if !(-MAX_PITCH..=MAX_PITCH).contains(&p) {
+                bail!("pitch must be between -{} and {} semitones", MAX_PITCH, MAX_PITCH);
+            }
+            Command::Pitch(p)
+        },
+        "sort" => {
+            let column = require_arg()?;
+            if !COLUMNS.contains(&column) {
+                bail!("unknown column \"{}\", expected one of {}", column, COLUMNS.join(", "));
+            }
+            Command::Sort(column.to_string())
+        },
+        "tag" => {
+            let arg = require_arg()?;
+            let (field, value) = match arg.find(char::is_whitespace) {
+                Some(i) => (&arg[..i], arg[i..].trim()),
+                None => bail!("usage: :tag <field> <value>")
+            };
+            if !TAG_FIELDS.contains(&field) {
+                bail!("unknown tag \"{}\", expected one of {}", field, TAG_FIELDS.join(", "));
+            }
+            Command::Tag(field.to_string(), value.to_string())
+        },
+        "analyze" => match arg {
+            "" => Command::Analyze(false),
+            "tags" => Command::Analyze(true),
+            _ => bail!("usage: :analyze [tags]")
+        },
+        "eq" => {
+            let arg = require_arg()?;
+            match arg.strip_prefix("save") {
+                Some("") => bail!("usage: :eq save <name>"),
+                Some(name) if name.starts_with(char::is_whitespace) => Command::EqSave(name.trim().to_string()),
+                _ if arg == "bypass" => Command::EqBypass,
+                _ => Command::EqPreset(arg.to_string())
+            }
+        },
+        "q" | "quit" => Command::Quit,
+        "" => bail!("no command given"),
+        _ => bail!("unknown command \"{}\"", name)
+    };
+    Ok(command)
+}
+
+fn parse_seek(arg: &str) -> Result<Seek, Error> {
+    let invalid = || format_err!("invalid position \"{}\", expected [+-]m:ss or seconds", arg);
+    let seek = if let Some(rest) = arg.strip_prefix('+') {
+        Seek::Forward(parse_time(rest).ok_or_else(invalid)?)
+    } else if let Some(rest) = arg.strip_prefix('-') {
+        Seek::Backward(parse_time(rest).ok_or_else(invalid)?)
+    } else {
+        Seek::To(parse_time(arg).ok_or_else(invalid)?)
+    };
+    Ok(seek)
+}
+
+/// Accepts `90`, `1:30` and `1:01:30`, minutes and seconds after the first part stay below 60.
+pub fn parse_time(s: &str) -> Option<Duration> {
+    let mut seconds: u64 = 0;
+    for (i, part) in s.split(':').enumerate() {
+        let value = part.parse::<u64>().ok()?;
+        if i > 0 && value >= 60 {
+            return None;
+        }
+        seconds = seconds * 60 + value;
+    }
+    Some(Duration::from_secs(seconds))
+}
+
+/// Possible full inputs for `input`, completing command names, paths of `:add`,
+/// playlist names of `:save` / `:load` and columns of `:sort`.
+pub fn complete(input: &str) -> Vec<String> {
+    let (name, arg) = match input.find(' ') {
+        Some(i) => (&input[..i], input[i..].trim_start()),
+        None => {
+            return COMMANDS
+                .iter()
+                .filter(|c| c.0.starts_with(input))
+                .map(|c| format!("{} ", c.0))
+                .collect();
+        }
+    };
+
+    let candidates: Vec<String> = match name {
+        "add" => complete_path(arg),
+        "save" | "load" | "w" | "e" => list_playlists().into_iter().filter(|p| p.starts_with(arg)).collect(),
+        "sort" => COLUMNS.iter().filter(|c| c.starts_with(arg)).map(|c| c.to_string()).collect(),
+        "eq" => list_presets()
+            .into_iter()
+            .chain(vec!["save ".to_string(), "bypass".to_string()])
+            .filter(|p| p.starts_with(arg))
+            .collect(),
+        "analyze" => ["tags"].iter().filter(|c| c.starts_with(arg)).map(|c| c.to_string()).collect(),
+        "tag" => TAG_FIELDS.iter().filter(|c| c.starts_with(arg)).map(|c| format!("{} ", c)).collect(),
+        _ => vec![]
+    };
+    candidates.into_iter().map(|c| format!("{} {}", name, c)).collect()
+}
+
+fn complete_path(arg: &str) -> Vec<String> {
+    let (dir, prefix) = match arg.rfind('/') {
+        Some(i) => (&arg[..=i], &arg[i + 1..]),
+        None => ("", arg)
+    };
+    let read_from = if dir.is_empty() { PathBuf::from(".") } else { expand_tilde(Path::new(dir)) };
+
+    let mut candidates = vec![];
+    if let Ok(entries) = fs::read_dir(read_from) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.')) {
+                let suffix = if entry.path().is_dir() { "/" } else { "" };
+                candidates.push(format!("{}{}{}", dir, name, suffix));
+            }
+        }
+    }
+    candidates.sort();
+    candidates
+}
+
+pub fn get_common_prefix(candidates: &[String]) -> String {
+    let first = match candidates.first() {
+        Some(f) => f,
+        None => return String::new()
+    };
+    let mut prefix: String = first.to_string();
+    for c in &candidates[1..] {
+        while !c.starts_with(&prefix) {
+            prefix.pop();
+        }
+    }
+    prefix
+}
+
+
+/// Input state of the `:` command line.
+#[derive(Debug, Default)]
+pub struct CommandLine {
+    pub input: String,
+    pub history: Vec<String>,
+    pub completions: Vec<String>,
+    history_index: Option<usize>,
+}
+
+impl CommandLine {
+    pub fn new() -> CommandLine {
+        CommandLine::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.input.clear();
+        self.completions.clear();
+        self.history_index = None;
+    }
+
+    pub fn push_char(&mut self, c: char) {
+        self.input.push(c);
+        self.completions.clear();
+    }
+
+    pub fn backspace(&mut self) {
+        self.input.pop();
+        self.completions.clear();
+    }
+
+    /// Completes as far as all candidates agree, remaining candidates are kept in `completions`.
+    pub fn complete(&mut self) {
+        let candidates = complete(&self.input);
+        let prefix = get_common_prefix(&candidates);
+        if prefix.len() > self.input.len() {
+            self.input = prefix;
+        }
+        self.completions = if candidates.len() > 1 { candidates } else { vec![] };
+    }
+
+    pub fn history_prev(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let i = match self.history_index {
+            Some(i) => i.saturating_sub(1),
+            None => self.history.len() - 1
+        };
+        self.history_index = Some(i);
+        self.input = self.history[i].clone();
+    }
+
+    pub fn history_next(&mut self) {
+        match self.history_index {
+            Some(i) if i + 1 < self.history.len() => {
+                self.history_index = Some(i + 1);
+                self.input = self.history[i + 1].clone();
+            },
+            Some(_) => {
+                self.history_index = None;
+                self.input.clear();
+            },
+            None => {}
+        }
+    }
+
+    /// Returns the submitted input and records it in history.
+    pub fn submit(&mut self) -> String {
+        let input = self.input.trim().to_string();
+        if !input.is_empty() && self.history.last() != Some(&input) {
+            self.history.push(input.clone());
+        }
+        self.clear();
+        input
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_error(input: &str) -> String {
+        parse(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn seek_reads_absolute_and_relative_positions() {
+        assert_eq!(parse("seek +1:30").unwrap(), Command::Seek(Seek::Forward(Duration::from_secs(90))));
+        assert_eq!(parse("seek -10").unwrap(), Command::Seek(Seek::Backward(Duration::from_secs(10))));
+        assert_eq!(parse("seek 1:01:30").unwrap(), Command::Seek(Seek::To(Duration::from_secs(3690))));
+        assert!(parse("seek 1:x").is_err());
+    }
+
+    #[test]
+    fn parse_time_rejects_minutes_and_seconds_from_60() {
+        assert_eq!(parse_time("75"), Some(Duration::from_secs(75)));
+        assert_eq!(parse_time("1:59"), Some(Duration::from_secs(119)));
+        assert_eq!(parse_time("1:75"), None);
+        assert_eq!(parse_time("1:60:00"), None);
+        assert_eq!(parse_time("1:"), None);
+    }
+
+    #[test]
+    fn missing_argument_shows_usage() {
+        assert_eq!(get_error("seek"), "usage: :seek <[+-]m:ss>");
+        assert_eq!(get_error("save  "), "usage: :save <playlist>");
+        assert_eq!(get_error("eq save"), "usage: :eq save <name>");
+    }
+
+    #[test]
+    fn unknown_or_missing_command_is_an_error() {
+        assert_eq!(get_error("play"), "unknown command \"play\"");
+        assert_eq!(get_error(""), "no command given");
+    }
+
+    #[test]
+    fn volume_is_clamped_to_100() {
+        assert_eq!(parse("vol 150").unwrap(), Command::Volume(100));
+        assert_eq!(parse("volume 40").unwrap(), Command::Volume(40));
+        assert_eq!(get_error("vol 300"), "invalid volume \"300\"");
+        assert_eq!(get_error("vol -1"), "invalid volume \"-1\"");
+    }
+
+    #[test]
+    fn eq_save_takes_a_name() {
+        assert_eq!(parse("eq save late night").unwrap(), Command::EqSave("late night".to_string()));
+        assert_eq!(parse("eq saved").unwrap(), Command::EqPreset("saved".to_string()));
+        assert_eq!(parse("eq bypass").unwrap(), Command::EqBypass);
+    }
+
+    #[test]
+    fn common_prefix_of_candidates() {
+        assert_eq!(get_common_prefix(&[]), "");
+        assert_eq!(get_common_prefix(&["save ".to_string(), "seek ".to_string(), "sort ".to_string()]), "s");
+        assert_eq!(get_common_prefix(&["añade".to_string(), "añadir".to_string()]), "añad");
+    }
+
+    #[test]
+    fn history_walks_back_and_forward() {
+        let mut line = CommandLine::new();
+        for input in &["vol 50", "seek 1:00", "seek 1:00", " q "] {
+            line.input = input.to_string();
+            line.submit();
+        }
+        // repeated inputs are kept once, and trimmed.
+        assert_eq!(line.history, vec!["vol 50", "seek 1:00", "q"]);
+
+        line.history_prev();
+        assert_eq!(line.input, "q");
+        line.history_prev();
+        line.history_prev();
+        line.history_prev();
+        assert_eq!(line.input, "vol 50");
+        line.history_next();
+        assert_eq!(line.input, "seek 1:00");
+        line.history_next();
+        line.history_next();
+        assert_eq!(line.input, "");
+        line.history_next();
+        assert_eq!(line.input, "");
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs;
+use std::collections::BTreeMap;
+use std::path::{ Path, PathBuf };
+
+use failure::Error;
+use ignore::overrides::OverrideBuilder;
+use serde::{ Deserialize, Serialize };
+
+use crate::theme::{ ColorsConfig, Theme, ThemeConfig };
+use tsiangt::equalizer::{ self, EqSettings };
+use tsiangt::replaygain::{ ReplayGainMode, ReplayGainSettings };
+use tsiangt::tempo::{ TempoSettings, MAX_PITCH, MAX_SPEED, MIN_SPEED };
+
+
+const CONFIG_DIR: &str = "tsiangt";
+const CONFIG_FILE: &str = "config.toml";
+
+
+/// Effective configuration, built from `~/.config/tsiangt/config.toml`
+/// and then overridden by command line flags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub library: LibraryConfig,
+    pub ui: UiConfig,
+    pub playback: PlaybackConfig,
+    /// user-defined themes, selected by `ui.theme`.
+    pub themes: BTreeMap<String, ThemeConfig>,
+    /// colors of older configs, still laid over the selected theme.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<ColorsConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LibraryConfig {
+    /// Music roots, existing ones are browsable as top-level entries when there are several.
+    pub music_dirs: Vec<PathBuf>,
+    /// Glob patterns (gitignore syntax) skipped while scanning.
+    pub exclude: Vec<String>,
+    /// Folders jumped to with `'<key>`, by key.
+    pub bookmarks: BTreeMap<String, PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// one of "playlist", "library" or "search".
+    pub default_tab: String,
+    /// columns of track tables, see `COLUMNS`.
+    pub columns: Vec<String>,
+    /// built-in theme (see `theme::THEMES`) or a key of `themes`.
+    pub theme: String,
+    /// rows kept visible above and below the cursor when scrolling.
+    pub scroll_off: usize,
+    /// scroll fields of the selected row that don't fit their column.
+    pub marquee: bool,
+    /// start the directory panel as a tree instead of a list.
+    pub directory_tree: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaybackConfig {
+    /// 0 - 100.
+    pub volume: u8,
+    pub replay_gain: ReplayGainMode,
+    /// dB added to replay gain, -15 - 15.
+    pub replay_gain_preamp: f32,
+    /// lower the replay gain of tracks that would clip.
+    pub replay_gain_prevent_clipping: bool,
+    /// play the next playlist item when the current one ends.
+    pub autoplay: bool,
+    /// start over from the first item at the end of the playlist.
+    pub repeat: bool,
+    /// "device", "null" or "wav:<file>", see `output::OUTPUTS`.
+    pub output: String,
+    /// equalizer preset at start, built-in (see `equalizer::PRESETS`) or saved.
+    pub equalizer: String,
+    /// start with the equalizer bypassed.
+    pub equalizer_bypass: bool,
+    /// 0.5 - 3, pitch stays the same.
+    pub speed: f32,
+    /// semitones, -12 - 12.
+    pub pitch: f32,
+}
+
+pub const COLUMNS: [&str; 6] = [
+    "title",
+    "artist",
+    "album",
+    "year",
+    "track",
+    "duration"
+];
+
+impl Default for LibraryConfig {
+    fn default() -> LibraryConfig {
+        LibraryConfig {
+            music_dirs: dirs::audio_dir().into_iter().collect(),
+            exclude: Vec::new(),
+            bookmarks: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for UiConfig {
+    fn default() -> UiConfig {
+        UiConfig {
+            default_tab: "playlist".to_string(),
+            columns: vec!["title".to_string(), "artist".to_string(), "album".to_string()],
+            theme: "default".to_string(),
+            scroll_off: 2,
+            marquee: false,
+            directory_tree: false,
+        }
+    }
+}
+
+impl Default for PlaybackConfig {
+    fn default() -> PlaybackConfig {
+        PlaybackConfig {
+            volume: 100,
+            replay_gain: ReplayGainMode::Off,
+            replay_gain_preamp: 0.0,
+            replay_gain_prevent_clipping: true,
+            autoplay: true,
+            repeat: false,
+            output: "device".to_string(),
+            equalizer: "flat".to_string(),
+            equalizer_bypass: false,
+            speed: 1.0,
+            pitch: 0.0,
+        }
+    }
+}
+
+impl Config {
+
+    /// Reads the config from `path`, or from the default location when `None`.
+    /// A missing default config file is not an error, defaults are used instead.
+    pub fn load(path: Option<&Path>) -> Result<Config, Error> {
+        let path = match path {
+            Some(p) => p.to_path_buf(),
+            None => match default_config_path() {
+                Some(p) if p.exists() => p,
+                _ => return Ok(Config::default()),
+            }
+        };
+
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format_err!("cannot read config {}: {}", path.display(), e))?;
+        let mut config: Config = toml::from_str(&content)
+            .map_err(|e| format_err!("invalid config {}: {}", path.display(), e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    pub fn validate(&mut self) -> Result<(), Error> {
+        self.library.music_dirs = self.library.music_dirs.iter().map(|d| expand_tilde(d)).collect();
+        for (key, path) in self.library.bookmarks.iter_mut() {
+            if key.chars().count() != 1 {
+                bail!("bookmark key \"{}\" must be a single character", key);
+            }
+            *path = expand_tilde(path);
+        }
+
+        let mut builder = OverrideBuilder::new("/");
+        for pattern in &self.library.exclude {
+            builder.add(&format!("!{}", pattern))
+                .map_err(|e| format_err!("invalid exclude pattern \"{}\": {}", pattern, e))?;
+        }
+
+        if get_tab_index(&self.ui.default_tab).is_none() {
+            bail!("unknown default_tab \"{}\"", self.ui.default_tab);
+        }
+
+        for c in &self.ui.columns {
+            if !COLUMNS.contains(&c.as_str()) {
+                bail!("unknown column \"{}\", expected one of {}", c, COLUMNS.join(", "));
+            }
+        }
+        if self.ui.columns.is_empty() {
+            bail!("at least one column is required");
+        }
+
+        self.get_theme()?;
+
+        if self.playback.volume > 100 {
+            self.playback.volume = 100;
+        }
+        if !(-15.0..=15.0).contains(&self.playback.replay_gain_preamp) {
+            bail!("replay_gain_preamp must be between -15 and 15 dB");
+        }
+        self.get_equalizer()?;
+        if !(MIN_SPEED..=MAX_SPEED).contains(&self.playback.speed) {
+            bail!("speed must be between {} and {}", MIN_SPEED, MAX_SPEED);
+        }
+        if !(-MAX_PITCH..=MAX_PITCH).contains(&self.playback.pitch) {
+            bail!("pitch must be between -{} and {} semitones", MAX_PITCH, MAX_PITCH);
+        }
+        Ok(())
+    }
+
+    /// The first existing music root.
+    pub fn get_music_dir(&self) -> Option<&PathBuf> {
+        self.library.music_dirs.iter().find(|d| d.is_dir())
+    }
+
+    /// Existing music roots, unmounted ones are skipped.
+    pub fn get_music_dirs(&self) -> Vec<&PathBuf> {
+        self.library.music_dirs.iter().filter(|d| d.is_dir()).collect()
+    }
+
+    /// The music root `path` is under, the deepest one when roots are nested.
+    pub fn get_root_of(&self, path: &Path) -> Option<&PathBuf> {
+        self.library.music_dirs
+            .iter()
+            .filter(|d| path.starts_with(d))
+            .max_by_key(|d| d.components().count())
+    }
+
+    pub fn get_bookmark(&self, key: char) -> Option<&PathBuf> {
+        self.library.bookmarks.get(&key.to_string())
+    }
+
+    pub fn get_theme(&self) -> Result<Theme, Error> {
+        let theme = Theme::resolve(&self.ui.theme, &self.themes)?;
+        match &self.colors {
+            Some(colors) => colors.apply(theme),
+            None => Ok(theme)
+        }
+    }
+
+    pub fn get_volume(&self) -> f32 {
+        f32::from(self.playback.volume) / 100.0
+    }
+
+    pub fn get_replay_gain(&self) -> ReplayGainSettings {
+        ReplayGainSettings {
+            mode: self.playback.replay_gain,
+            preamp: self.playback.replay_gain_preamp,
+            prevent_clipping: self.playback.replay_gain_prevent_clipping,
+        }
+    }
+
+    pub fn get_tempo(&self) -> TempoSettings {
+        TempoSettings { speed: self.playback.speed, pitch: self.playback.pitch }
+    }
+
+    pub fn get_equalizer(&self) -> Result<EqSettings, Error> {
+        let gains = equalizer::get_preset(&self.playback.equalizer).ok_or_else(|| format_err!(
+            "unknown equalizer preset \"{}\", expected one of {}",
+            self.playback.equalizer,
+            equalizer::list_presets().join(", ")
+        ))?;
+        Ok(EqSettings { gains, bypass: self.playback.equalizer_bypass })
+    }
+}
+
+pub fn default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join(CONFIG_DIR).join(CONFIG_FILE))
+}
+
+pub fn get_tab_index(title: &str) -> Option<usize> {
+    match title {
+        "playlist" => Some(0),
+        "library" => Some(1),
+        "search" => Some(2),
+        _ => None
+    }
+}
+
+pub fn expand_tilde(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tui::style::Color;
+
+    #[test]
+    fn colors_table_overrides_theme() {
+        let config: Config = toml::from_str("[colors]\nactive = \"red\"\ninactive = \"#010203\"\n").unwrap();
+        let theme = config.get_theme().unwrap();
+        assert_eq!(theme.active.fg, Color::Red);
+        assert_eq!(theme.border.fg, Color::Rgb(1, 2, 3));
+        assert_eq!(theme.playing, Theme::default().playing);
+    }
+
+    #[test]
+    fn unknown_color_in_colors_table_is_an_error() {
+        let config: Config = toml::from_str("[colors]\nplaying = \"nope\"\n").unwrap();
+        assert!(config.get_theme().is_err());
+    }
+}
@@ -3,9 +3,14 @@ use std::iter::Iterator;
 
 use tui::buffer::Buffer;
 use tui::layout::Rect;
-use tui::style::{ Style, Color};
+use tui::style::Style;
 use tui::widgets::{Block, Widget};
 
+use crate::text::truncate;
+
+/// columns in front of every cell: select symbol and spacing.
+const CELL_PADDING: usize = 3;
+
 /// Holds data to be displayed in a Table widget
 pub enum Row<D, I>
 where
@@ -36,7 +41,8 @@ where
     /// Width of each column (if the total width is greater than the widget width some columns may
     /// not be displayed)
     widths: &'a [u16],
-    height: usize,
+    /// index of the first row in `rows`, rows are expected to be the visible ones only.
+    offset: usize,
     selected: Option<usize>,
     /// Space between each column
     column_spacing: u16,
@@ -61,7 +67,7 @@ where
             header: H::default(),
             header_style: Style::default(),
             widths: &[],
-            height: 0,
+            offset: 0,
             selected: None,
             rows: R::default(),
             column_spacing: 1,
@@ -88,7 +94,7 @@ where
             header_style: Style::default(),
             widths: &[],
             selected: None,
-            height: 0,
+            offset: 0,
             rows,
             column_spacing: 1,
             select_symbol: String::from(">"),
@@ -146,6 +152,11 @@ where
         self
     }
 
+    pub fn offset(mut self, offset: usize) -> Table<'a, T, H, I, D, R> {
+        self.offset = offset;
+        self
+    }
+
      pub fn select_symbol(mut self, symbol: String) -> Table<'a, T,H,I,D,R>{
         self.select_symbol = symbol;
         self
@@ -173,23 +184,7 @@ where
             None => area,
         };
 
-        let table_height = table_area.height as usize - 2;
-
-        // Use highlight_style only if something is selected
-        let selected = match self.selected {
-            Some(i) => Some(i),
-            None => None,
-        };
-
-        let offset = if let Some(s) = selected {
-            if s >= table_height {
-                s - table_height + 1
-            } else {
-                0
-            }
-        } else {
-            0
-        };
+        let offset = self.offset;
 
         // Set the background
         self.background(table_area, buf, self.style.bg);
@@ -210,7 +205,8 @@ where
         if y < table_area.bottom() {
             x = table_area.left();
             for (w, t) in widths.iter().zip(self.header.by_ref()) {
-                buf.set_string(x, y, format!("    {}", t), self.header_style);
+                let text = truncate(&t.to_string(), (*w as usize).saturating_sub(CELL_PADDING + 1));
+                buf.set_stringn(x, y, format!("    {}", text), *w as usize, self.header_style);
                 x += *w + self.column_spacing;
             }
         }
@@ -220,13 +216,15 @@ where
         let default_style = Style::default();
         if y < table_area.bottom() {
             let remaining = (table_area.bottom() - y) as usize;
-            for (i, row) in self.rows.by_ref().skip(offset as usize).take(remaining).enumerate() {
+            for (i, row) in self.rows.by_ref().take(remaining).enumerate() {
                 let (data, style) = match row {
                     Row::Data(d) => (d, default_style),
                     Row::StyledData(d, s) => (d, s),
                 };
                 x = table_area.left();
                 for (i_w, (w, elt) ) in widths.iter().zip(data).enumerate() {
+                    // cut by display width, so wide glyphs don't spill into the next column.
+                    let elt = truncate(&elt.to_string(), (*w as usize).saturating_sub(CELL_PADDING));
                     if let Some(sl) =  self.selected {
                         if sl == i + offset {
                             buf.set_stringn(
@@ -253,3 +251,17 @@ where
         }
     }
 }
+
+
+/// Resets every cell of its area, used to draw popups over other widgets.
+pub struct Clear;
+
+impl Widget for Clear {
+    fn draw(&mut self, area: Rect, buf: &mut Buffer) {
+        for x in area.left()..area.right() {
+            for y in area.top()..area.bottom() {
+                buf.get_mut(x, y).reset();
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{ OnceLock, RwLock };
+
+use failure::Error;
+use serde::{ Deserialize, Serialize };
+
+use crate::loudness::Measurement;
+use crate::replaygain::ReplayGain;
+
+
+const DATABASE_FILE: &str = "tsiangt/library.toml";
+
+
+/// What tsiangt learned about songs beyond their tags, by file path.
+/// Lives in `~/.local/share/tsiangt/library.toml`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Database {
+    pub loudness: BTreeMap<String, LoudnessEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LoudnessEntry {
+    pub track: Measurement,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub album: Option<Measurement>,
+}
+
+/// loaded on first use, shared by every thread.
+static SHARED: OnceLock<RwLock<Database>> = OnceLock::new();
+
+pub fn get_database_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join(DATABASE_FILE))
+}
+
+impl Database {
+    /// empty when there is no database yet.
+    pub fn load() -> Result<Database, Error> {
+        let path = match get_database_path() {
+            Some(p) if p.is_file() => p,
+            _ => return Ok(Database::default())
+        };
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format_err!("cannot read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format_err!("invalid {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let path = get_database_path().ok_or_else(|| format_err!("no data directory to keep the library database in"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn get_replay_gain(&self, path: &str) -> Option<ReplayGain> {
+        self.loudness.get(path).map(|entry| ReplayGain {
+            track_gain: Some(entry.track.get_gain() as f32),
+            track_peak: Some(entry.track.peak as f32),
+            album_gain: entry.album.map(|m| m.get_gain() as f32),
+            album_peak: entry.album.map(|m| m.peak as f32),
+        })
+    }
+}
+
+fn get_shared() -> &'static RwLock<Database> {
+    SHARED.get_or_init(|| RwLock::new(Database::load().unwrap_or_default()))
+}
+
+/// measured gain of `path`, used for songs without gain tags.
+pub fn get_replay_gain(path: &str) -> Option<ReplayGain> {
+    get_shared().read().ok()?.get_replay_gain(path)
+}
+
+/// records measurements and saves the database.
+pub fn store_loudness(entries: Vec<(String, LoudnessEntry)>) -> Result<(), Error> {
+    let mut database = get_shared().write().map_err(|_| format_err!("library database is unavailable"))?;
+    database.loudness.extend(entries);
+    database.save()
+}
@@ -0,0 +1,78 @@
+use std::sync::Mutex;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+
+/// Second order IIR filter (direct form I), coefficients normalized by `a0`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Biquad {
+    b0: f64,
+    b1: f64,
+    b2: f64,
+    a1: f64,
+    a2: f64,
+    x1: f64,
+    x2: f64,
+    y1: f64,
+    y2: f64,
+}
+
+impl Biquad {
+    pub fn new(b: [f64; 3], a: [f64; 3]) -> Biquad {
+        let mut biquad = Biquad::default();
+        biquad.set_coefficients(b, a);
+        biquad
+    }
+
+    /// changes the response, samples in flight are kept.
+    pub fn set_coefficients(&mut self, b: [f64; 3], a: [f64; 3]) {
+        self.b0 = b[0] / a[0];
+        self.b1 = b[1] / a[0];
+        self.b2 = b[2] / a[0];
+        self.a1 = a[1] / a[0];
+        self.a2 = a[2] / a[0];
+    }
+
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2 - self.a1 * self.y1 - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+
+/// Settings shared by `Player` and the sources it plays, so changes are heard right away.
+#[derive(Debug, Default)]
+pub struct Shared<T> {
+    value: Mutex<T>,
+    /// bumped on every change, sources only lock `value` when it moved.
+    version: AtomicUsize,
+}
+
+impl<T> Shared<T>
+    where T: Copy + Default
+{
+    pub fn new(value: T) -> Shared<T> {
+        Shared {
+            value: Mutex::new(value),
+            version: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get(&self) -> T {
+        self.value.lock().map(|v| *v).unwrap_or_default()
+    }
+
+    pub fn set(&self, value: T) {
+        if let Ok(mut v) = self.value.lock() {
+            *v = value;
+            self.version.fetch_add(1, Ordering::Release);
+        }
+    }
+
+    pub fn get_version(&self) -> usize {
+        self.version.load(Ordering::Acquire)
+    }
+}
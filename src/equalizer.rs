@@ -0,0 +1,326 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use failure::Error;
+use rodio::Source;
+use serde::{ Deserialize, Serialize };
+
+use crate::dsp::{ Biquad, Shared };
+
+
+/// center frequencies (Hz) of the bands, an octave apart.
+pub const BANDS: [f64; 10] = [31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0];
+/// band gains are limited to +/- this many dB.
+pub const MAX_GAIN: f32 = 12.0;
+/// bandwidth of about an octave.
+const Q: f64 = 1.41;
+
+/// user presets, built-in ones aren't saved.
+const PRESETS_FILE: &str = "tsiangt/equalizer.toml";
+
+/// gain (dB) of every band.
+pub type Gains = [f32; 10];
+
+/// built-in presets, by name.
+pub const PRESETS: [(&str, Gains); 7] = [
+    ("flat", [0.0; 10]),
+    ("bass boost", [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+    ("treble boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0]),
+    ("vocal", [-3.0, -2.0, -1.0, 1.0, 3.0, 4.0, 3.0, 1.0, 0.0, -1.0]),
+    ("rock", [4.0, 3.0, 1.0, -1.0, -2.0, -1.0, 1.0, 3.0, 4.0, 4.0]),
+    ("classical", [3.0, 2.0, 1.0, 0.0, 0.0, 0.0, -1.0, -1.0, 1.0, 2.0]),
+    ("loudness", [5.0, 4.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0, 4.0, 5.0]),
+];
+
+
+/// What the equalizer does, changed while playing through `SharedEq`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EqSettings {
+    pub gains: Gains,
+    /// plays as if every gain was 0, keeping `gains` for later.
+    pub bypass: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct PresetFile {
+    presets: BTreeMap<String, Gains>,
+}
+
+impl EqSettings {
+    pub fn is_active(&self) -> bool {
+        !self.bypass && self.gains.iter().any(|g| *g != 0.0)
+    }
+
+    /// adds `step` dB to band `band`, within `MAX_GAIN`.
+    pub fn adjust(&mut self, band: usize, step: f32) {
+        if let Some(gain) = self.gains.get_mut(band) {
+            *gain = (*gain + step).clamp(-MAX_GAIN, MAX_GAIN);
+        }
+    }
+}
+
+/// "31", "1k", "16k".
+pub fn get_band_label(band: usize) -> String {
+    match BANDS[band] {
+        f if f >= 1000.0 => format!("{}k", f / 1000.0),
+        f => format!("{}", f)
+    }
+}
+
+
+pub fn get_presets_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join(PRESETS_FILE))
+}
+
+fn load_user_presets() -> BTreeMap<String, Gains> {
+    get_presets_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|content| toml::from_str::<PresetFile>(&content).ok())
+        .map(|f| f.presets)
+        .unwrap_or_default()
+}
+
+/// built-in presets first, then saved ones.
+pub fn list_presets() -> Vec<String> {
+    let mut names: Vec<String> = PRESETS.iter().map(|p| p.0.to_string()).collect();
+    let saved = load_user_presets().into_iter().map(|p| p.0);
+    names.extend(saved.filter(|n| !PRESETS.iter().any(|p| p.0 == n)));
+    names
+}
+
+pub fn get_preset(name: &str) -> Option<Gains> {
+    match PRESETS.iter().find(|p| p.0 == name) {
+        Some(p) => Some(p.1),
+        None => load_user_presets().get(name).cloned()
+    }
+}
+
+/// saves `gains` as `name`, replacing a saved preset of that name.
+pub fn save_preset(name: &str, gains: Gains) -> Result<(), Error> {
+    if PRESETS.iter().any(|p| p.0 == name) {
+        bail!("\"{}\" is a built-in preset", name);
+    }
+    let path = get_presets_path().ok_or_else(|| format_err!("no data directory to keep presets in"))?;
+    let mut file = PresetFile { presets: load_user_presets() };
+    file.presets.insert(name.to_string(), gains);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, toml::to_string(&file)?)?;
+    Ok(())
+}
+
+
+pub type SharedEq = Shared<EqSettings>;
+
+
+/// Source filtered by the 10 peaking filters of the equalizer, one set per channel.
+pub struct Equalized<S> {
+    source: S,
+    shared: Arc<SharedEq>,
+    /// version of `shared` the filters were built for.
+    version: Option<usize>,
+    is_active: bool,
+    /// every filter boosting is offset by this, so loud songs don't clip.
+    headroom: f64,
+    filters: Vec<Vec<Biquad>>,
+    channel: usize,
+    /// sample rate the filters were built for.
+    rate: u32,
+}
+
+impl<S> Equalized<S>
+    where S: Source<Item = i16>
+{
+    pub fn new(source: S, shared: Arc<SharedEq>) -> Equalized<S> {
+        let channels = usize::from(source.channels().max(1));
+        Equalized {
+            rate: source.sample_rate(),
+            source,
+            shared,
+            version: None,
+            is_active: false,
+            headroom: 1.0,
+            filters: vec![vec![Biquad::default(); BANDS.len()]; channels],
+            channel: 0,
+        }
+    }
+
+    /// new filters when a frame of the source has another rate or channel count.
+    /// decoders report the format of a frame once its first sample is read, not when
+    /// `current_frame_len` of the previous one runs out, so it's compared on every frame.
+    fn follow_format(&mut self) {
+        let channels = usize::from(self.source.channels().max(1));
+        let rate = self.source.sample_rate();
+        if channels != self.filters.len() || rate != self.rate {
+            self.filters = vec![vec![Biquad::default(); BANDS.len()]; channels];
+            self.rate = rate;
+            self.version = None;
+        }
+    }
+
+    /// rebuilds coefficients, keeping filter state so changes don't click.
+    fn update(&mut self) {
+        let version = self.shared.get_version();
+        if self.version == Some(version) {
+            return;
+        }
+        self.version = Some(version);
+
+        let settings = self.shared.get();
+        self.is_active = settings.is_active();
+        let max_gain = settings.gains.iter().cloned().fold(0.0, f32::max);
+        self.headroom = 10f64.powf(-f64::from(max_gain) / 20.0);
+
+        let rate = f64::from(self.rate);
+        for (band, gain) in settings.gains.iter().enumerate() {
+            // bands above nyquist can't be filtered, they stay flat.
+            let (b, a) = match BANDS[band] < rate / 2.0 {
+                true => get_peaking_coefficients(BANDS[band], f64::from(*gain), rate),
+                false => ([1.0, 0.0, 0.0], [1.0, 0.0, 0.0])
+            };
+            for filters in self.filters.iter_mut() {
+                filters[band].set_coefficients(b, a);
+            }
+        }
+    }
+}
+
+/// RBJ cookbook peaking filter.
+fn get_peaking_coefficients(freq: f64, gain: f64, rate: f64) -> ([f64; 3], [f64; 3]) {
+    let a = 10f64.powf(gain / 40.0);
+    let w0 = 2.0 * PI * freq / rate;
+    let alpha = w0.sin() / (2.0 * Q);
+    (
+        [1.0 + alpha * a, -2.0 * w0.cos(), 1.0 - alpha * a],
+        [1.0 + alpha / a, -2.0 * w0.cos(), 1.0 - alpha / a]
+    )
+}
+
+impl<S> Iterator for Equalized<S>
+    where S: Source<Item = i16>
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        let sample = self.source.next()?;
+        if self.channel == 0 {
+            self.follow_format();
+            self.update();
+        }
+        let channel = self.channel;
+        self.channel = (self.channel + 1) % self.filters.len();
+        if !self.is_active {
+            return Some(sample);
+        }
+
+        let mut x = f64::from(sample) * self.headroom;
+        for filter in self.filters[channel].iter_mut() {
+            x = filter.process(x);
+        }
+        Some(x.round().clamp(f64::from(i16::MIN), f64::from(i16::MAX)) as i16)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.source.size_hint()
+    }
+}
+
+impl<S> Source for Equalized<S>
+    where S: Source<Item = i16>
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// |H(e^jw)| in dB of a biquad at `freq`.
+    fn get_response(b: [f64; 3], a: [f64; 3], freq: f64, rate: f64) -> f64 {
+        let w = 2.0 * PI * freq / rate;
+        let magnitude = |c: [f64; 3]| {
+            let re = c[0] + c[1] * w.cos() + c[2] * (2.0 * w).cos();
+            let im = -c[1] * w.sin() - c[2] * (2.0 * w).sin();
+            (re * re + im * im).sqrt()
+        };
+        20.0 * (magnitude(b) / magnitude(a)).log10()
+    }
+
+    fn equalize(settings: EqSettings, samples: &[i16]) -> Vec<i16> {
+        let shared = Arc::new(SharedEq::default());
+        shared.set(settings);
+        Equalized::new(SamplesBuffer::new(2, 44100, samples.to_vec()), shared).collect()
+    }
+
+    #[test]
+    fn peaking_filter_has_the_band_gain_at_its_center() {
+        for (freq, gain) in &[(31.0, 6.0), (1000.0, -12.0), (16000.0, 12.0)] {
+            let (b, a) = get_peaking_coefficients(*freq, *gain, 44100.0);
+            let response = get_response(b, a, *freq, 44100.0);
+            assert!((response - gain).abs() < 1e-6, "{} Hz: {} dB", freq, response);
+        }
+    }
+
+    #[test]
+    fn flat_and_bypassed_equalizer_keep_samples() {
+        let samples: Vec<i16> = (0..4410).map(|i| ((i * 37) % 20000) as i16 - 10000).collect();
+        assert_eq!(equalize(EqSettings::default(), &samples), samples);
+
+        let bypassed = EqSettings { gains: PRESETS[1].1, bypass: true };
+        assert_eq!(equalize(bypassed, &samples), samples);
+
+        let boosted = EqSettings { gains: PRESETS[1].1, bypass: false };
+        assert_ne!(equalize(boosted, &samples), samples);
+    }
+
+    #[test]
+    fn filters_follow_format_changes() {
+        let shared = Arc::new(SharedEq::default());
+        shared.set(EqSettings { gains: PRESETS[1].1, bypass: false });
+        let mono: Vec<i16> = (0..4410).map(|i| ((i * 37) % 20000) as i16 - 10000).collect();
+        let stereo: Vec<i16> = (0..4410).map(|i| ((i * 91) % 16000) as i16 - 8000).collect();
+        let get_parts = || vec![SamplesBuffer::new(1, 44100, mono.clone()), SamplesBuffer::new(2, 22050, stereo.clone())];
+
+        // filtered as if each part played on its own.
+        let expected: Vec<i16> = get_parts()
+            .into_iter()
+            .flat_map(|part| Equalized::new(part, shared.clone()).collect::<Vec<i16>>())
+            .collect();
+        let chained: Vec<i16> = Equalized::new(rodio::source::from_iter(get_parts()), shared.clone()).collect();
+        assert_eq!(chained, expected);
+    }
+
+    #[test]
+    fn adjust_stays_within_max_gain() {
+        let mut settings = EqSettings::default();
+        settings.adjust(0, 10.0);
+        settings.adjust(0, 10.0);
+        settings.adjust(9, -30.0);
+        assert_eq!(settings.gains[0], MAX_GAIN);
+        assert_eq!(settings.gains[9], -MAX_GAIN);
+        settings.adjust(BANDS.len(), 1.0);
+        assert_eq!(settings.gains[1..9], [0.0; 8]);
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+use std::io;
+
+use failure::Fail;
+
+
+/// Failures shown to the user instead of panicking, they flow through `failure::Error`.
+#[derive(Debug)]
+pub enum TsiangtError {
+    NoMusicDirectory,
+    NoOutputDevice,
+    Open {
+        path: String,
+        cause: io::Error,
+    },
+    Decode {
+        path: String,
+        reason: String,
+    },
+    Tag {
+        path: String,
+        reason: String,
+    },
+    ReadDirectory {
+        path: String,
+        cause: io::Error,
+    },
+}
+
+impl fmt::Display for TsiangtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TsiangtError::NoMusicDirectory => write!(f, "no music directory found, set one with -d <directory>"),
+            TsiangtError::NoOutputDevice => write!(f, "no audio output device found"),
+            TsiangtError::Open { path, cause } => write!(f, "cannot open {}: {}", path, cause),
+            TsiangtError::Decode { path, reason } => write!(f, "cannot decode {}: {}", path, reason),
+            TsiangtError::Tag { path, reason } => write!(f, "cannot read tags of {}: {}", path, reason),
+            TsiangtError::ReadDirectory { path, cause } => write!(f, "cannot read directory {}: {}", path, cause),
+        }
+    }
+}
+
+/// written out rather than derived, `failure_derive` expands to impls the compiler now warns about.
+impl Fail for TsiangtError {
+    fn cause(&self) -> Option<&dyn Fail> {
+        match self {
+            TsiangtError::Open { cause, .. } | TsiangtError::ReadDirectory { cause, .. } => Some(cause),
+            _ => None
+        }
+    }
+}
@@ -5,32 +5,42 @@ use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
-use termion::event::Key;
+use signal_hook::iterator::Signals;
+use termion::event::{ Event as TermEvent, Key, MouseEvent };
 use termion::input::TermRead;
 
+
+/// quit on the first three, suspend / redraw on the last two.
+pub const SIGNALS: [i32; 5] = [
+    libc::SIGINT,
+    libc::SIGTERM,
+    libc::SIGHUP,
+    libc::SIGTSTP,
+    libc::SIGCONT
+];
+
 pub enum Event<I> {
     Input(I),
+    Mouse(MouseEvent),
+    Signal(i32),
     Tick,
 }
 
 /// A small event handler that wrap termion input and tick events. Each event
-/// type is handled in its own thread and returned to a common `Receiver`
+/// type is handled in its own thread and returned to a common `Receiver`,
+/// the threads are never joined, they end with the process.
 pub struct Events {
     rx: mpsc::Receiver<Event<Key>>,
-    input_handle: thread::JoinHandle<()>,
-    tick_handle: thread::JoinHandle<()>,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Config {
-    pub exit_key: Key,
     pub tick_rate: Duration,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
-            exit_key: Key::Esc,
             tick_rate: Duration::from_millis(250),
         }
     }
@@ -43,37 +53,39 @@ impl Events {
 
     pub fn with_config(config: Config) -> Events {
         let (tx, rx) = mpsc::channel();
-        let input_handle = {
-            let tx = tx.clone();
-            thread::spawn(move || {
-                let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if tx.send(Event::Input(key)).is_err() {
-                            return;
-                        }
-                        if key == config.exit_key {
-                            return;
-                        }
-                    }
+        let input_tx = tx.clone();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for evt in stdin.events() {
+                let event = match evt {
+                    Ok(TermEvent::Key(key)) => Event::Input(key),
+                    Ok(TermEvent::Mouse(mouse)) => Event::Mouse(mouse),
+                    _ => continue
+                };
+                // keep reading until the app quits, `esc` no longer means exit
+                // (it also cancels command line).
+                if input_tx.send(event).is_err() {
+                    return;
                 }
-            })
-        };
-        let tick_handle = {
-            let tx = tx.clone();
+            }
+        });
+        let tick_tx = tx.clone();
+        thread::spawn(move || {
+            while tick_tx.send(Event::Tick).is_ok() {
+                thread::sleep(config.tick_rate);
+            }
+        });
+        // without signal handling tsiangt still runs, killing it just leaves the terminal as is.
+        if let Ok(signals) = Signals::new(SIGNALS) {
             thread::spawn(move || {
-                let tx = tx.clone();
-                loop {
-                    tx.send(Event::Tick).unwrap();
-                    thread::sleep(config.tick_rate);
+                for signal in signals.forever() {
+                    if tx.send(Event::Signal(signal)).is_err() {
+                        return;
+                    }
                 }
-            })
-        };
-        Events {
-            rx,
-            input_handle,
-            tick_handle,
+            });
         }
+        Events { rx }
     }
 
     pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
@@ -0,0 +1,230 @@
+use termion::event::Key;
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Context {
+    Global,
+    Playlist,
+    Library,
+    Search,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Quit,
+    Suspend,
+    Help,
+    MessageLog,
+    OutputPicker,
+    Equalizer,
+    SpeedUp,
+    SpeedDown,
+    PitchUp,
+    PitchDown,
+    CommandLine,
+    TabPlaylist,
+    TabLibrary,
+    TabSearch,
+    NextTab,
+    TogglePause,
+    Stop,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Top,
+    Bottom,
+    HalfPageUp,
+    HalfPageDown,
+    PageUp,
+    PageDown,
+    JumpToPlaying,
+    JumpToLetter,
+    Filter,
+    NextMatch,
+    PrevMatch,
+    ToggleMark,
+    VisualMode,
+    Play,
+    AddToPlaylist,
+    Remove,
+    MoveItemDown,
+    MoveItemUp,
+    Select,
+    ParentDirectory,
+    AddDirectory,
+    PlayDirectory,
+    EditSearch,
+    AddToQueue,
+    PlayNext,
+    ToggleTree,
+    JumpToBookmark,
+    Expand,
+    Collapse,
+}
+
+pub struct Binding {
+    pub context: Context,
+    pub keys: &'static [Key],
+    pub action: Action,
+    pub description: &'static str,
+}
+
+
+pub const CONTEXTS: [Context; 4] = [
+    Context::Global,
+    Context::Playlist,
+    Context::Library,
+    Context::Search
+];
+
+/// Every keybinding, context-specific bindings take precedence over global ones.
+/// This table is also what the help overlay (`?`) lists.
+pub const BINDINGS: &[Binding] = &[
+    Binding { context: Context::Global, keys: &[Key::Char('q'), Key::Esc], action: Action::Quit, description: "quit (esc clears filter first)" },
+    Binding { context: Context::Global, keys: &[Key::Ctrl('z')], action: Action::Suspend, description: "suspend to shell, fg resumes" },
+    Binding { context: Context::Global, keys: &[Key::Char('?')], action: Action::Help, description: "show / hide this help" },
+    Binding { context: Context::Global, keys: &[Key::Char('L')], action: Action::MessageLog, description: "show / hide recent messages" },
+    Binding { context: Context::Global, keys: &[Key::Char('O')], action: Action::OutputPicker, description: "pick audio output device" },
+    Binding { context: Context::Global, keys: &[Key::Char('=')], action: Action::Equalizer, description: "show / hide equalizer" },
+    Binding { context: Context::Global, keys: &[Key::Char('>')], action: Action::SpeedUp, description: "play 0.1x faster, pitch stays the same" },
+    Binding { context: Context::Global, keys: &[Key::Char('<')], action: Action::SpeedDown, description: "play 0.1x slower" },
+    Binding { context: Context::Global, keys: &[Key::Char('}')], action: Action::PitchUp, description: "raise pitch by a semitone" },
+    Binding { context: Context::Global, keys: &[Key::Char('{')], action: Action::PitchDown, description: "lower pitch by a semitone" },
+    Binding { context: Context::Global, keys: &[Key::Char(':')], action: Action::CommandLine, description: "open command line" },
+    Binding { context: Context::Global, keys: &[Key::F(1)], action: Action::TabPlaylist, description: "switch to playlist tab" },
+    Binding { context: Context::Global, keys: &[Key::F(2)], action: Action::TabLibrary, description: "switch to library tab" },
+    Binding { context: Context::Global, keys: &[Key::F(3)], action: Action::TabSearch, description: "switch to search tab" },
+    Binding { context: Context::Global, keys: &[Key::Char('\t')], action: Action::NextTab, description: "switch to next tab" },
+    Binding { context: Context::Global, keys: &[Key::Char(' ')], action: Action::TogglePause, description: "resume / pause" },
+    Binding { context: Context::Global, keys: &[Key::Char('s')], action: Action::Stop, description: "stop" },
+    Binding { context: Context::Global, keys: &[Key::Char('k'), Key::Up], action: Action::MoveUp, description: "move up, [count]k moves count rows" },
+    Binding { context: Context::Global, keys: &[Key::Char('j'), Key::Down], action: Action::MoveDown, description: "move down, [count]j moves count rows" },
+    Binding { context: Context::Global, keys: &[Key::Char('g')], action: Action::Top, description: "gg: go to first row, [count]gg to row count" },
+    Binding { context: Context::Global, keys: &[Key::Char('G'), Key::End], action: Action::Bottom, description: "go to last row, [count]G to row count" },
+    Binding { context: Context::Global, keys: &[Key::Ctrl('u')], action: Action::HalfPageUp, description: "half page up" },
+    Binding { context: Context::Global, keys: &[Key::Ctrl('d')], action: Action::HalfPageDown, description: "half page down" },
+    Binding { context: Context::Global, keys: &[Key::Ctrl('b'), Key::PageUp], action: Action::PageUp, description: "page up" },
+    Binding { context: Context::Global, keys: &[Key::Ctrl('f'), Key::PageDown], action: Action::PageDown, description: "page down" },
+    Binding { context: Context::Global, keys: &[Key::Char('o')], action: Action::JumpToPlaying, description: "go to playing song" },
+    Binding { context: Context::Global, keys: &[Key::Char('f')], action: Action::JumpToLetter, description: "f<letter>: go to next row starting with letter" },
+    Binding { context: Context::Global, keys: &[Key::Char('\'')], action: Action::JumpToBookmark, description: "'<key>: open bookmarked folder in library" },
+    Binding { context: Context::Global, keys: &[Key::Char('/')], action: Action::Filter, description: "highlight rows matching typed text" },
+    Binding { context: Context::Global, keys: &[Key::Char('n')], action: Action::NextMatch, description: "go to next match" },
+    Binding { context: Context::Global, keys: &[Key::Char('N')], action: Action::PrevMatch, description: "go to previous match" },
+
+    Binding { context: Context::Global, keys: &[Key::Char('m')], action: Action::ToggleMark, description: "mark / unmark song (esc clears marks)" },
+    Binding { context: Context::Global, keys: &[Key::Char('V')], action: Action::VisualMode, description: "start / end selecting a range of songs" },
+
+    Binding { context: Context::Global, keys: &[Key::Char('e')], action: Action::AddToQueue, description: "append selected songs to up-next queue" },
+    Binding { context: Context::Global, keys: &[Key::Char('E')], action: Action::PlayNext, description: "play selected songs right after current one" },
+
+    Binding { context: Context::Playlist, keys: &[Key::Char('\n')], action: Action::Play, description: "play selected song" },
+    Binding { context: Context::Playlist, keys: &[Key::Char('d'), Key::Delete], action: Action::Remove, description: "remove selected songs" },
+    Binding { context: Context::Playlist, keys: &[Key::Char('J')], action: Action::MoveItemDown, description: "move selected songs down" },
+    Binding { context: Context::Playlist, keys: &[Key::Char('K')], action: Action::MoveItemUp, description: "move selected songs up" },
+    Binding { context: Context::Playlist, keys: &[Key::Char('h'), Key::Left], action: Action::MoveLeft, description: "focus playlist" },
+    Binding { context: Context::Playlist, keys: &[Key::Char('l'), Key::Right], action: Action::MoveRight, description: "focus up-next queue" },
+
+    Binding { context: Context::Library, keys: &[Key::Char('\n')], action: Action::Select, description: "open directory / add selected songs to playlist" },
+    Binding { context: Context::Library, keys: &[Key::Char('h'), Key::Left], action: Action::MoveLeft, description: "focus directory panel" },
+    Binding { context: Context::Library, keys: &[Key::Char('l'), Key::Right], action: Action::MoveRight, description: "focus files panel" },
+    Binding { context: Context::Library, keys: &[Key::Char('b')], action: Action::ParentDirectory, description: "go to parent directory" },
+    Binding { context: Context::Library, keys: &[Key::Char('t')], action: Action::ToggleTree, description: "show directories as a tree / as a list" },
+    Binding { context: Context::Library, keys: &[Key::Char('+')], action: Action::Expand, description: "expand directory (tree view)" },
+    Binding { context: Context::Library, keys: &[Key::Char('-')], action: Action::Collapse, description: "collapse directory, or the one it is in (tree view)" },
+    Binding { context: Context::Library, keys: &[Key::Char('a')], action: Action::AddDirectory, description: "append every song under selected directory" },
+    Binding { context: Context::Library, keys: &[Key::Char('A')], action: Action::PlayDirectory, description: "replace playlist with selected directory and play" },
+
+    Binding { context: Context::Search, keys: &[Key::Char('\n')], action: Action::EditSearch, description: "start / finish editing query" },
+    Binding { context: Context::Search, keys: &[Key::Char('a')], action: Action::AddToPlaylist, description: "add selected songs to playlist" },
+];
+
+
+impl Context {
+    pub fn from_tab(title: &str) -> Context {
+        match title {
+            "playlist" => Context::Playlist,
+            "library" => Context::Library,
+            "search" => Context::Search,
+            _ => Context::Global
+        }
+    }
+
+    pub fn get_title(self) -> &'static str {
+        match self {
+            Context::Global => "global",
+            Context::Playlist => "playlist",
+            Context::Library => "library",
+            Context::Search => "search",
+        }
+    }
+}
+
+pub fn get_action(context: Context, key: Key) -> Option<Action> {
+    let find = |c: Context| BINDINGS
+        .iter()
+        .find(|b| b.context == c && b.keys.contains(&key))
+        .map(|b| b.action);
+
+    find(context).or_else(|| find(Context::Global))
+}
+
+pub fn get_bindings(context: Context) -> impl Iterator<Item = &'static Binding> {
+    BINDINGS.iter().filter(move |b| b.context == context)
+}
+
+pub fn format_key(key: &Key) -> String {
+    match key {
+        Key::Char('\n') => "enter".to_string(),
+        Key::Char('\t') => "tab".to_string(),
+        Key::Char(' ') => "space".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        Key::Up => "up".to_string(),
+        Key::Down => "down".to_string(),
+        Key::Left => "left".to_string(),
+        Key::Right => "right".to_string(),
+        Key::PageUp => "pageup".to_string(),
+        Key::PageDown => "pagedown".to_string(),
+        Key::Home => "home".to_string(),
+        Key::End => "end".to_string(),
+        Key::Backspace => "backspace".to_string(),
+        Key::Delete => "delete".to_string(),
+        Key::Esc => "esc".to_string(),
+        _ => "?".to_string()
+    }
+}
+
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter().map(format_key).collect::<Vec<String>>().join(", ")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_keys_switch_tabs() {
+        assert_eq!(get_action(Context::Library, Key::F(1)), Some(Action::TabPlaylist));
+        assert_eq!(get_action(Context::Playlist, Key::F(2)), Some(Action::TabLibrary));
+        assert_eq!(get_action(Context::Search, Key::F(3)), Some(Action::TabSearch));
+    }
+
+    /// digits are left to count prefixes.
+    #[test]
+    fn digits_are_not_bound() {
+        for c in "0123456789".chars() {
+            assert_eq!(get_action(Context::Playlist, Key::Char(c)), None);
+        }
+    }
+
+    #[test]
+    fn context_bindings_win_over_global_ones() {
+        assert_eq!(get_action(Context::Library, Key::Char('h')), Some(Action::MoveLeft));
+        assert_eq!(get_action(Context::Search, Key::Char('a')), Some(Action::AddToPlaylist));
+    }
+}
@@ -0,0 +1,27 @@
+//! Music library, tags, playlists and playback of tsiangt, without the terminal UI.
+//!
+//! - `library`: scanning directories for playable files.
+//! - `track`: `Track` model, reading and writing id3 tags.
+//! - `playlist`: m3u playlists.
+//! - `player`: playback engine, driven through channels.
+//! - `replaygain`: leveling tracks with their ReplayGain / R128 tags.
+//! - `loudness`: EBU R128 loudness and true peak measurement.
+//! - `database`: measured loudness of songs, kept between runs.
+//! - `equalizer`: 10-band graphic equalizer and its presets.
+//! - `tempo`: playback speed and pitch.
+//! - `output`: where the player sends audio: sound card, nothing or a wav file.
+
+#[macro_use] extern crate failure;
+
+pub mod database;
+pub mod dsp;
+pub mod equalizer;
+pub mod error;
+pub mod library;
+pub mod loudness;
+pub mod output;
+pub mod player;
+pub mod playlist;
+pub mod replaygain;
+pub mod tempo;
+pub mod track;
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use failure::Error;
+use ignore::{ WalkBuilder, DirEntry };
+use ignore::overrides::{ Override, OverrideBuilder };
+
+use crate::error::TsiangtError;
+use crate::track::Track;
+
+
+pub fn is_not_hidden(entry: &Path) -> bool {
+    match entry.file_name() {
+        Some(name) => !name.to_string_lossy().starts_with('.'),
+        // `/` or a path ending with `..`.
+        None => true
+    }
+}
+
+/// whether `path` has the extension of a playable file.
+pub fn is_music_in_folder(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("mp3" | "flac" | "ogg" | "wav" | "m4a"))
+}
+
+fn is_music(entry: &DirEntry) -> bool {
+    // vanished or unreadable entries are skipped.
+    match fs::metadata(entry.path()) {
+        Ok(metadata) => !metadata.is_dir() && is_music_in_folder(entry.path()),
+        Err(_) => false
+    }
+}
+
+pub fn is_excluded(path: &Path, exclude: &[String]) -> bool {
+    match path.parent() {
+        Some(parent) => get_exclude_override(parent, exclude)
+            .matched(path, path.is_dir())
+            .is_ignore(),
+        None => false
+    }
+}
+
+/// builds gitignore-style matcher from `exclude` patterns,
+/// invalid patterns are skipped (they are rejected by `Config::validate` anyway).
+pub fn get_exclude_override(root: &Path, exclude: &[String]) -> Override {
+    let mut builder = OverrideBuilder::new(root);
+    for pattern in exclude {
+        let _ = builder.add(&format!("!{}", pattern));
+    }
+    builder.build().unwrap_or_else(|_| Override::empty())
+}
+
+/// entries of `root`, sorted. unreadable entries are skipped.
+pub fn get_list_of_paths(root: &Path) -> Result<Vec<PathBuf>, Error> {
+    let entries = fs::read_dir(root)
+        .map_err(|e| TsiangtError::ReadDirectory { path: root.display().to_string(), cause: e })?;
+
+    let mut result: Vec<PathBuf> = entries.flatten().map(|e| e.path()).collect();
+    result.sort();
+    Ok(result)
+}
+
+
+pub fn get_tracks_from_path(path: &Path, exclude: &[String]) -> Vec<Track> {
+    WalkBuilder::new(path)
+        .overrides(get_exclude_override(path, exclude))
+        .build()
+        .flatten()
+        .filter(is_music)
+        .filter_map(|entry| Track::new(entry.into_path()).ok())
+        .collect()
+}
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use failure::Error;
+use rodio::Source;
+use serde::{ Deserialize, Serialize };
+
+use crate::database::{ self, LoudnessEntry };
+use crate::dsp::Biquad;
+use crate::error::TsiangtError;
+use crate::track::{ Track, write_replay_gain };
+
+
+/// loudness ReplayGain 2 levels tracks to.
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+
+/// EBU R128 gating.
+const BLOCK_MS: u32 = 400;
+const STEP_MS: u32 = 100;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// true peak is measured on the signal upsampled this many times.
+const OVERSAMPLING: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+
+/// Integrated loudness (LUFS) and true peak (1.0 = full scale) of a track or an album.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Measurement {
+    pub loudness: f64,
+    pub peak: f64,
+}
+
+/// Progress of `analyze_tracks`.
+#[derive(Debug, Clone)]
+pub enum Progress {
+    Track(Track, Result<Measurement, String>),
+    Album(String, Measurement),
+}
+
+/// Result of one file: energy of every gating block, and its true peak.
+pub struct Analysis {
+    blocks: Vec<f64>,
+    peak: f64,
+}
+
+impl Measurement {
+    /// ReplayGain in dB, relative to `REFERENCE_LOUDNESS`.
+    pub fn get_gain(&self) -> f64 {
+        REFERENCE_LOUDNESS - self.loudness
+    }
+}
+
+impl Analysis {
+    /// none for silence.
+    pub fn get_measurement(&self) -> Option<Measurement> {
+        get_integrated_loudness(&self.blocks).map(|loudness| Measurement { loudness, peak: self.peak })
+    }
+}
+
+/// Decodes `path` and measures it, following ITU-R BS.1770-4 / EBU R128.
+pub fn analyze_file(path: &Path) -> Result<Analysis, Error> {
+    let file = File::open(path)
+        .map_err(|e| TsiangtError::Open { path: path.display().to_string(), cause: e })?;
+    let source = rodio::Decoder::new(BufReader::new(file))
+        .map_err(|e| TsiangtError::Decode { path: path.display().to_string(), reason: e.to_string() })?;
+
+    let channels = usize::from(source.channels().max(1));
+    let rate = source.sample_rate();
+    let mut filters: Vec<KWeighting> = (0..channels).map(|_| KWeighting::new(rate)).collect();
+    let mut peaks: Vec<TruePeak> = (0..channels).map(|_| TruePeak::new()).collect();
+    let weights: Vec<f64> = (0..channels).map(|c| get_channel_weight(c, channels)).collect();
+
+    // squared sums of 100ms steps, a block is the last four of them.
+    let step_len = (rate * STEP_MS / 1000).max(1) as usize;
+    let steps_per_block = (BLOCK_MS / STEP_MS) as usize;
+    let mut steps: Vec<f64> = Vec::new();
+    let mut step_sum = 0.0;
+    let mut step_frames = 0;
+    let mut blocks = Vec::new();
+
+    let mut channel = 0;
+    for sample in source {
+        let x = f64::from(sample) / 32768.0;
+        peaks[channel].process(x);
+        let y = filters[channel].process(x);
+        step_sum += weights[channel] * y * y;
+
+        channel += 1;
+        if channel < channels {
+            continue;
+        }
+        channel = 0;
+        step_frames += 1;
+        if step_frames == step_len {
+            steps.push(step_sum);
+            step_sum = 0.0;
+            step_frames = 0;
+            if steps.len() >= steps_per_block {
+                let sum: f64 = steps[steps.len() - steps_per_block..].iter().sum();
+                blocks.push(sum / (step_len * steps_per_block) as f64);
+            }
+        }
+    }
+
+    Ok(Analysis {
+        blocks,
+        peak: peaks.iter().map(|p| p.peak).fold(0.0, f64::max),
+    })
+}
+
+/// gated loudness of blocks, none when every block is below the absolute gate.
+pub fn get_integrated_loudness(blocks: &[f64]) -> Option<f64> {
+    let loudness = |z: f64| -0.691 + 10.0 * z.log10();
+    let mean = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+
+    let above_absolute: Vec<f64> = blocks.iter().cloned().filter(|z| loudness(*z) > ABSOLUTE_GATE).collect();
+    if above_absolute.is_empty() {
+        return None;
+    }
+    let relative_gate = loudness(mean(&above_absolute)) + RELATIVE_GATE;
+    let gated: Vec<f64> = above_absolute.into_iter().filter(|z| loudness(*z) > relative_gate).collect();
+    if gated.is_empty() {
+        return None;
+    }
+    Some(loudness(mean(&gated)))
+}
+
+/// Measures `tracks`, and every album among them as a whole (same album and album artist).
+/// `report` gets each result as it's done, measured tracks are returned with their album measurement.
+pub fn analyze_tracks<F>(tracks: &[Track], mut report: F) -> Vec<(Track, Measurement, Option<Measurement>)>
+    where F: FnMut(Progress)
+{
+    let mut albums: BTreeMap<(String, String), Vec<&Track>> = BTreeMap::new();
+    for track in tracks {
+        albums.entry((track.album_artist.clone(), track.album.clone())).or_default().push(track);
+    }
+
+    let mut results = Vec::new();
+    for ((_, album), tracks) in albums {
+        let mut album_blocks = Vec::new();
+        let mut album_peak: f64 = 0.0;
+        let mut measured = Vec::new();
+        for track in tracks {
+            let result = analyze_file(Path::new(&track.file_path))
+                .map_err(|e| e.to_string())
+                .and_then(|a| {
+                    let measurement = a.get_measurement().ok_or_else(|| "silent".to_string())?;
+                    album_blocks.extend(a.blocks);
+                    album_peak = album_peak.max(a.peak);
+                    Ok(measurement)
+                });
+            if let Ok(m) = result {
+                measured.push((track.clone(), m));
+            }
+            report(Progress::Track(track.clone(), result));
+        }
+
+        // songs without an album share no loudness.
+        let album_measurement = match album.is_empty() {
+            true => None,
+            false => get_integrated_loudness(&album_blocks).map(|loudness| Measurement { loudness, peak: album_peak })
+        };
+        if let Some(m) = album_measurement {
+            report(Progress::Album(album, m));
+        }
+        results.extend(measured.into_iter().map(|(t, m)| (t, m, album_measurement)));
+    }
+    results
+}
+
+/// Keeps results of `analyze_tracks` in the library database, and in ReplayGain tags with `write_tags`.
+/// Returns the songs whose tags couldn't be written, with the reason.
+pub fn save_results(results: &[(Track, Measurement, Option<Measurement>)], write_tags: bool) -> Result<Vec<String>, Error> {
+    let entries = results
+        .iter()
+        .map(|(t, track, album)| (t.file_path.clone(), LoudnessEntry { track: *track, album: *album }))
+        .collect();
+    database::store_loudness(entries)?;
+
+    let mut failed = vec![];
+    if write_tags {
+        for (t, track, album) in results {
+            if let Err(e) = write_replay_gain(Path::new(&t.file_path), track, album.as_ref()) {
+                failed.push(format!("{}: {}", t.file_path, e));
+            }
+        }
+    }
+    Ok(failed)
+}
+
+/// BS.1770 channel weights, surround channels of 5.1 count more and LFE not at all.
+fn get_channel_weight(channel: usize, channels: usize) -> f64 {
+    match (channels, channel) {
+        (6, 3) => 0.0,
+        (6, 4) | (6, 5) => 1.41,
+        _ => 1.0
+    }
+}
+
+
+/// K-weighting: high shelf modelling the head, then high pass.
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    /// coefficients for any sample rate, as derived by libebur128.
+    fn new(rate: u32) -> KWeighting {
+        let rate = f64::from(rate);
+
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let shelf = Biquad::new(
+            [vh + vb * k / q + k * k, 2.0 * (k * k - vh), vh - vb * k / q + k * k],
+            [1.0 + k / q + k * k, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k]
+        );
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (PI * f0 / rate).tan();
+        // the numerator stays unnormalized, as in libebur128.
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]
+        );
+
+        KWeighting { shelf, high_pass }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(x))
+    }
+}
+
+
+/// Peak of the signal upsampled by `OVERSAMPLING` with a windowed sinc, catching peaks between samples.
+struct TruePeak {
+    history: [f64; TAPS_PER_PHASE],
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new() -> TruePeak {
+        TruePeak { history: [0.0; TAPS_PER_PHASE], peak: 0.0 }
+    }
+
+    fn process(&mut self, x: f64) {
+        self.history.rotate_right(1);
+        self.history[0] = x;
+        self.peak = self.peak.max(x.abs());
+        for phase in 1..OVERSAMPLING {
+            let y: f64 = self.history
+                .iter()
+                .enumerate()
+                .map(|(i, h)| h * get_interpolation_tap(i * OVERSAMPLING + phase))
+                .sum();
+            self.peak = self.peak.max(y.abs());
+        }
+    }
+}
+
+/// tap `i` of the interpolation filter, hann windowed sinc.
+fn get_interpolation_tap(i: usize) -> f64 {
+    let len = (TAPS_PER_PHASE * OVERSAMPLING) as f64;
+    let t = (i as f64 - len / 2.0) / OVERSAMPLING as f64;
+    let sinc = if t == 0.0 { 1.0 } else { (PI * t).sin() / (PI * t) };
+    let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / len).cos();
+    sinc * window
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use hound::{ SampleFormat, WavSpec, WavWriter };
+
+    const RATE: u32 = 48000;
+
+    /// analysis of a mono wav file holding `samples` (1.0 = full scale).
+    fn analyze_samples(name: &str, samples: &[f64]) -> Analysis {
+        let path = env::temp_dir().join(format!("tsiangt-loudness-{}-{}.wav", name, std::process::id()));
+        let spec = WavSpec { channels: 1, sample_rate: RATE, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for x in samples {
+            writer.write_sample((x * 32767.0).round() as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let analysis = analyze_file(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        analysis
+    }
+
+    /// `seconds` of a sine of `amplitude` starting at `phase`.
+    fn get_sine(freq: f64, amplitude: f64, phase: f64, seconds: f64) -> Vec<f64> {
+        (0..(f64::from(RATE) * seconds) as usize)
+            .map(|i| amplitude * (2.0 * PI * freq * i as f64 / f64::from(RATE) + phase).sin())
+            .collect()
+    }
+
+    #[test]
+    fn sine_at_minus_20_dbfs_is_minus_23_lufs() {
+        let amplitude = 10f64.powf(-20.0 / 20.0);
+        let measurement = analyze_samples("sine", &get_sine(1000.0, amplitude, 0.0, 3.0))
+            .get_measurement()
+            .unwrap();
+        assert!((measurement.loudness + 23.0).abs() < 0.1, "{}", measurement.loudness);
+        assert!((measurement.get_gain() - 5.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let analysis = analyze_samples("silence", &[0.0; RATE as usize * 2]);
+        assert!(!analysis.blocks.is_empty());
+        assert!(analysis.get_measurement().is_none());
+        assert!(get_integrated_loudness(&[]).is_none());
+    }
+
+    #[test]
+    fn true_peak_is_found_between_samples() {
+        // a quarter of the sample rate, 45° off: every sample is at 0.707 of the peak.
+        let samples = get_sine(f64::from(RATE) / 4.0, 0.5, PI / 4.0, 1.0);
+        let sample_peak = samples.iter().fold(0.0, |p: f64, x| p.max(x.abs()));
+        let analysis = analyze_samples("peak", &samples);
+        assert!(analysis.peak > sample_peak * 1.2, "{} <= {}", analysis.peak, sample_peak);
+        assert!((analysis.peak - 0.5).abs() < 0.05, "{}", analysis.peak);
+    }
+}
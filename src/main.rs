@@ -3,27 +3,39 @@ mod App;
 mod events;
 mod ui;
 mod custom_widgets;
-mod player;
+mod config;
+mod theme;
+mod keys;
+mod command;
+mod text;
+mod message;
+mod term;
 
 use std::io;
-use std::path::PathBuf;
-use std::fs;
+use std::path::{ Path, PathBuf };
 use std::time::{ Duration };
 
 extern crate dirs;
 
 use termion::raw::IntoRawMode;
-use termion::event::Key;
+use termion::input::MouseTerminal;
 use tui::Terminal;
 use termion::screen::AlternateScreen;
 use tui::backend::TermionBackend;
-use ignore::{Walk, DirEntry};
 
 use crate::App::App as Application;
 use crate::App::*;
-use crate::App::{Track};
 use crate::events::{ Events, Event };
-use crate::player::{ Player };
+use crate::config::{ Config, get_tab_index };
+use tsiangt::library::get_tracks_from_path;
+use tsiangt::loudness::{ self, Progress };
+use tsiangt::replaygain::ReplayGainMode;
+use tsiangt::tempo::{ MAX_PITCH, MAX_SPEED, MIN_SPEED };
+use tsiangt::track::Track;
+use tsiangt::player::{ Player, PlayerChannels };
+use tsiangt::playlist::{ get_session_path, save_m3u };
+use tsiangt::output;
+use tsiangt::error::TsiangtError;
 
 use std::thread;
 use crossbeam_channel as channel;
@@ -33,7 +45,6 @@ use clap::{clap_app, crate_version};
 
 
 #[macro_use] extern crate failure;
-use failure::Error;
 
 
 
@@ -54,14 +65,98 @@ fn main() -> Result<(), failure::Error> {
                           (version:crate_version!())
                           (author:"Karnpapon Boonput")
                           (about:"tsiangt terminal music player!")
-                          (@arg directory: -d +takes_value "Sets directory")
+                          (@arg directory: -d +takes_value +multiple number_of_values(1) "Sets music directory, repeat for several roots")
+                          (@arg config: -c --config +takes_value "Sets config file (default: ~/.config/tsiangt/config.toml)")
+                          (@arg tab: -t --tab +takes_value "Sets default tab (playlist, library, search)")
+                          (@arg volume: --volume +takes_value "Sets volume (0 - 100)")
+                          (@arg theme: --theme +takes_value "Sets color theme (default, monochrome, ocean or user-defined)")
+                          (@arg output: --output +takes_value "Sets audio output: device (default), device:<name>, null or wav:<file>")
+                          (@arg list_devices: --("list-devices") "Prints the names of audio output devices and exits")
+                          (@arg speed: --speed +takes_value "Sets playback speed (0.5 - 3), pitch stays the same")
+                          (@arg pitch: --pitch +takes_value "Shifts pitch by semitones (-12 - 12)")
+                          (@arg equalizer: --eq +takes_value "Sets equalizer preset (flat, bass boost, vocal, .. or a saved one)")
+                          (@arg replay_gain: --("replay-gain") +takes_value "Sets replay-gain mode (off, track, album)")
+                          (@arg print_config: --("print-config") "Prints the effective config and exits")
+                          (@subcommand analyze =>
+                              (about: "Measures loudness of songs and albums, for replay-gain")
+                              (@arg paths: +required +multiple "Songs or directories to measure")
+                              (@arg write_tags: --("write-tags") "Also writes ReplayGain tags into the songs")
+                          )
     )
     .get_matches();
 
+    let mut config = Config::load(clap.value_of("config").map(Path::new))?;
+
+    // command line flags take precedence over config file.
+    if let Some(dirs) = clap.values_of("directory") {
+        config.library.music_dirs = dirs.map(PathBuf::from).collect();
+    }
+    if let Some(t) = clap.value_of("tab") {
+        if get_tab_index(t).is_none() {
+            bail!("unknown tab \"{}\"", t);
+        }
+        config.ui.default_tab = t.to_string();
+    }
+    if let Some(t) = clap.value_of("theme") {
+        config.ui.theme = t.to_string();
+        config.get_theme()?;
+    }
+    if let Some(v) = clap.value_of("volume") {
+        let v: u8 = v.parse().map_err(|_| format_err!("invalid volume \"{}\"", v))?;
+        config.playback.volume = v.min(100);
+    }
+    if let Some(o) = clap.value_of("output") {
+        config.playback.output = o.to_string();
+    }
+    if let Some(s) = clap.value_of("speed") {
+        let s: f32 = s.parse().map_err(|_| format_err!("invalid speed \"{}\"", s))?;
+        config.playback.speed = s.clamp(MIN_SPEED, MAX_SPEED);
+    }
+    if let Some(p) = clap.value_of("pitch") {
+        let p: f32 = p.parse().map_err(|_| format_err!("invalid pitch \"{}\"", p))?;
+        config.playback.pitch = p.clamp(-MAX_PITCH, MAX_PITCH);
+    }
+    if let Some(p) = clap.value_of("equalizer") {
+        config.playback.equalizer = p.to_string();
+        config.get_equalizer()?;
+    }
+    if let Some(m) = clap.value_of("replay_gain") {
+        config.playback.replay_gain = match m {
+            "off" => ReplayGainMode::Off,
+            "track" => ReplayGainMode::Track,
+            "album" => ReplayGainMode::Album,
+            _ => bail!("unknown replay-gain mode \"{}\"", m)
+        };
+    }
+
+    if clap.is_present("list_devices") {
+        for name in output::list_devices() {
+            println!("{}", name);
+        }
+        return Ok( () );
+    }
+
+    if let Some(matches) = clap.subcommand_matches("analyze") {
+        let paths: Vec<PathBuf> = matches.values_of("paths").unwrap_or_default().map(PathBuf::from).collect();
+        return analyze(&paths, matches.is_present("write_tags"), &config);
+    }
+
+    if clap.is_present("print_config") {
+        print!("{}", config.to_toml()?);
+        return Ok( () );
+    }
+
+    let music_dir = config.get_music_dir()
+        .cloned()
+        .ok_or(TsiangtError::NoMusicDirectory)?;
+
     let handle_events = Events::new();
-    let device = rodio::default_output_device().expect("No audio output device found");
+    let output = output::open(&config.playback.output)?;
 
+    term::save_mode()?;
+    term::set_panic_hook();
     let stdout = io::stdout().into_raw_mode()?;
+    let stdout = MouseTerminal::from(stdout);
     let stdout = AlternateScreen::from(stdout); // important!, separated into new screen (without data overlay with standard terminal screen).
     let backend = TermionBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
@@ -73,106 +168,148 @@ fn main() -> Result<(), failure::Error> {
     let (track_p_x, track_p_rx) = channel::bounded(0); // Track's play/pause.
     let (track_i_x, track_i_rx) = channel::bounded(0); // Track's information (when to start/stop).
     let (track_atp_x, track_atp_rx) = channel::bounded(0); // Track's autoplay.
-
-    let mut app = Application::new("/tsiangt/", track_x, track_p_x, track_i_rx, track_atp_x);  
-    let mut audio = Player::new(device, track_rx, track_p_rx, track_i_x, track_atp_rx);
-
-    match clap.value_of("directory"){
-        Some(c) => {
-            let d = PathBuf::from(c);
-            &app.set_init_directory(init_directory(&d));
-            &app.set_init_directory_files(init_tracks(&d));
-        },
-        _ => {
-            &app.set_init_directory( init_directory( &dirs::audio_dir().unwrap() ));
-            &app.set_init_directory_files(init_tracks(&dirs::audio_dir().unwrap()));
-        }
+    let (track_s_x, track_s_rx) = channel::bounded(0); // Track's seek position.
+    let (track_v_x, track_v_rx) = channel::bounded(0); // Volume.
+    let (tempo_x, tempo_rx) = channel::bounded(0); // Speed and pitch.
+    let (track_e_x, track_e_rx) = channel::unbounded(); // Unplayable tracks, never blocks the player.
+    let (output_x, output_rx) = channel::bounded(0); // Output to switch to.
+    let (output_event_x, output_event_rx) = channel::unbounded(); // Output switches and fallbacks.
+
+    let channels = PlayerChannels {
+        track_rx,
+        track_p_rx,
+        track_i_x,
+        track_atp_rx,
+        track_s_rx,
+        track_v_rx,
+        tempo_rx,
+        track_e_x,
+        output_rx,
+        output_x: output_event_x,
     };
-
-    if app.is_search_active {
-        get_input();
-    }
+    let mut audio = Player::new(output, channels);
+    audio.set_volume(config.get_volume());
+    audio.replay_gain = config.get_replay_gain();
+    audio.equalizer.set(config.get_equalizer()?);
+    audio.set_tempo(config.get_tempo());
+
+    let exclude = config.library.exclude.clone();
+    let channels = AppChannels {
+        track_x,
+        track_p_x,
+        track_i_rx,
+        track_atp_x,
+        track_s_x,
+        track_v_x,
+        tempo_x,
+        track_e_rx,
+        output_x,
+        output_rx: output_event_rx,
+    };
+    let mut app = Application::new("/tsiangt/", channels, audio.position.clone(), audio.equalizer.clone(), config);
+    app.open_library_root();
+    app.restore_session();
+    app.share_session();
+
+    // same as `on_exit`, for when tsiangt panics.
+    let stop_x = app.track_p_x.clone();
+    term::on_panic(move || {
+        // the player takes the second stop only once it's done with the first, a panicked one takes none.
+        for _ in 0..2 {
+            if stop_x.send_timeout(false, Duration::from_millis(250)).is_err() {
+                break;
+            }
+        }
+    });
+    let session = app.session.clone();
+    term::on_panic(move || {
+        if let (Some(path), Ok(tracks)) = (get_session_path(), session.try_lock()) {
+            let _ = save_m3u(&path, &tracks);
+        }
+    });
+    app.set_init_directory_files(init_tracks(&music_dir, &exclude));
 
     thread::spawn(move|| {
         loop{
 
-            if audio.handler.empty(){
-                if let Ok(()) = audio.track_i_x.send_timeout(true, Duration::from_millis(250)){}
-            } else if let Ok(()) = audio.track_i_x.send_timeout(false, Duration::from_millis(250)){
+            if audio.output.is_empty(){
+                if let Ok(()) = audio.channels.track_i_x.send_timeout(true, Duration::from_millis(250)){}
+            } else if let Ok(()) = audio.channels.track_i_x.send_timeout(false, Duration::from_millis(250)){
             }
 
-            if let Ok(track) = audio.track_rx.try_recv() {
-                audio.play(track)
+            if let Ok(track) = audio.channels.track_rx.try_recv() {
+                audio.play_or_report(track);
             }
 
 
-            if let Ok(next_in_queue) = audio.track_atp_rx.try_recv(){
-                audio.play(next_in_queue);
+            if let Ok(next_in_queue) = audio.channels.track_atp_rx.try_recv(){
+                audio.play_or_report(next_in_queue);
             }
 
-            match audio.track_p_rx.try_recv(){
+            match audio.channels.track_p_rx.try_recv(){
                 Ok(true) => audio.pause(),
                 Ok(false) => audio.stop(),
                 _ => {}
             }
+
+            if let Ok(position) = audio.channels.track_s_rx.try_recv() {
+                audio.seek(position);
+            }
+
+            if let Ok(volume) = audio.channels.track_v_rx.try_recv() {
+                audio.set_volume(volume);
+            }
+
+            if let Ok(tempo) = audio.channels.tempo_rx.try_recv() {
+                audio.set_tempo(tempo);
+            }
+
+            if let Ok(spec) = audio.channels.output_rx.try_recv() {
+                audio.switch_output(&spec);
+            }
+            audio.check_device();
+            audio.report_fallback();
+
+            audio.update_position();
         }
     });
      
    loop {
+        app.update_viewports(&ui::get_layout(&app, terminal.size()?));
         ui::draw(&mut terminal, &app)?;
-        if let Event::Input(input) = handle_events.next()? {
-         match input {
-                 Key::Char(c) => { app.on_key(c)}, 
-                 Key::Delete => { },
-                 Key::Esc => { app.is_quit = true },
-                 Key::Up => { app.reset_playing_track_index(); app.on_key_up() },
-                 Key::Down => { app.reset_playing_track_index(); app.on_key_down();},
-                 Key::Left => match app.tabs.get_current_title(){ 
-                     "playlist" => {},
-                     "library" => { 
-                         app.reset_playing_track_index();
-                         app.tabs.panels.prev_panel() 
-                     }
-                     _ => {}
-                 },
-                 Key::Right => match app.tabs.get_current_title(){ 
-                     "playlist" => {},
-                     "library" => { 
-                         if app.is_track_valid {
-                            app.reset_playing_track_index();
-                            app.tabs.panels.next_panel();
-                         }
-                     }
-                     _ => {}
-                 },
-                 _ => {}
-             }
-         }
+        match handle_events.next()? {
+            Event::Input(input) => app.on_input(input),
+            Event::Mouse(mouse) => {
+                let layout = ui::get_layout(&app, terminal.size()?);
+                app.on_mouse(mouse, &layout);
+            },
+            Event::Signal(libc::SIGTSTP) => term::suspend(&mut terminal)?,
+            // stopped from outside, the shell may have drawn over the screen.
+            Event::Signal(libc::SIGCONT) => term::redraw(&mut terminal)?,
+            Event::Signal(_) => app.is_quit = true,
+            Event::Tick => app.on_tick()
+        }
+
+        if app.is_suspend {
+            app.is_suspend = false;
+            term::suspend(&mut terminal)?;
+        }
 
                
+        app.on_player_errors();
+        app.on_output_events();
+        app.share_session();
+        app.on_analysis_events();
         if let Ok(true) = app.track_i_rx.recv_timeout(Duration::from_millis(250)){
-            if app.playlist.items.len() > 1 && app.is_playing {
-
-                // handle stop at the end of playlist(queue).
-                if app.get_next_playing_index().unwrap() > app.playlist.items.len() - 1 {
-                    app.is_playing = !app.is_playing; 
-                    app.track_p_x.send(false).unwrap();
-                    app.reset_playing_track_index();
-                } else {
-                // handle next song in queue.
-                 if let Ok(()) = app.track_atp_x.send(
-                     app.playlist.get_next_selected_item(app.get_next_playing_index().unwrap()).clone(), 
-                 ){};
-                 app.set_next_queue_playing_index();
-                }
-            }
-        } else {}  
+            app.on_track_end();
+        }
 
        
          if app.is_quit {
               break;
          }
     }
+        app.on_exit()?;
         Ok( () )
    
 }
@@ -185,6 +322,27 @@ fn main() -> Result<(), failure::Error> {
 //"# }
 
 
+/// `tsiangt analyze`, prints each measurement as it's done.
+fn analyze(paths: &[PathBuf], write_tags: bool, config: &Config) -> Result<(), failure::Error> {
+    let mut tracks = vec![];
+    for path in paths {
+        if path.is_dir() {
+            tracks.extend(get_tracks_from_path(path, &config.library.exclude));
+        } else {
+            tracks.push(Track::new(path.clone())?);
+        }
+    }
+    if tracks.is_empty() {
+        bail!("no song found");
+    }
 
-
-
+    let results = loudness::analyze_tracks(&tracks, |progress| match progress {
+        Progress::Track(track, Ok(m)) => println!("{:>7.2} LUFS  {:>+6.2} dB  peak {:.6}  {}", m.loudness, m.get_gain(), m.peak, track.file_path),
+        Progress::Track(track, Err(e)) => eprintln!("skipped {}: {}", track.file_path, e),
+        Progress::Album(album, m) => println!("{:>7.2} LUFS  {:>+6.2} dB  peak {:.6}  album {}", m.loudness, m.get_gain(), m.peak, album)
+    });
+    for e in loudness::save_results(&results, write_tags)? {
+        eprintln!("tags not written, {}", e);
+    }
+    Ok( () )
+}
@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use std::time::{ Duration, Instant };
+
+
+/// messages kept for the log view, older ones are dropped.
+pub const LOG_SIZE: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub level: Level,
+    pub text: String,
+    pub time: Instant,
+}
+
+/// Recent messages, the newest one is shown on the status bar for a while.
+#[derive(Debug, Default)]
+pub struct MessageLog {
+    messages: VecDeque<Message>,
+}
+
+impl Level {
+    pub fn get_title(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        }
+    }
+
+    /// how long the status bar shows a message, problems stay longer.
+    pub fn get_lifetime(self) -> Duration {
+        match self {
+            Level::Info => Duration::from_secs(3),
+            Level::Warning | Level::Error => Duration::from_secs(6),
+        }
+    }
+}
+
+impl Message {
+    pub fn is_expired(&self) -> bool {
+        self.time.elapsed() > self.level.get_lifetime()
+    }
+}
+
+impl MessageLog {
+    pub fn new() -> MessageLog {
+        MessageLog::default()
+    }
+
+    pub fn push(&mut self, level: Level, text: String) {
+        if self.messages.len() == LOG_SIZE {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(Message { level, text, time: Instant::now() });
+    }
+
+    /// newest message while it hasn't expired.
+    pub fn get_current(&self) -> Option<&Message> {
+        self.messages.back().filter(|m| !m.is_expired())
+    }
+
+    /// newest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Message> {
+        self.messages.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// "5s ago", "3m ago", "2h ago".
+pub fn format_age(time: Instant) -> String {
+    let seconds = time.elapsed().as_secs();
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        _ => format!("{}h ago", seconds / 3600),
+    }
+}
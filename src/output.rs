@@ -0,0 +1,481 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicBool, AtomicU32, Ordering };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use failure::Error;
+use hound::{ WavSpec, WavWriter, SampleFormat };
+use rodio::{ Device, Sample, Sink, Source };
+use rodio::source::UniformSourceIterator;
+
+use crate::error::TsiangtError;
+
+
+/// every kind of output `open` accepts.
+pub const OUTPUTS: [&str; 4] = [
+    "device",
+    "device:<name>",
+    "null",
+    "wav:<file>"
+];
+
+/// samples are consumed in chunks of this much audio.
+const CHUNK: Duration = Duration::from_millis(10);
+/// format of files written by `WavOutput`, every source is converted to it.
+const WAV_CHANNELS: u16 = 2;
+const WAV_SAMPLE_RATE: u32 = 44100;
+/// listing devices is slow, `DeviceOutput::check_device` does it at most this often.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// chunks written between two updates of the wav header.
+const WAV_FLUSH_CHUNKS: usize = 100;
+
+pub type BoxedSource = Box<dyn Source<Item = i16> + Send>;
+
+/// Where `Player` sends decoded audio. Playing a source replaces whatever played before.
+pub trait Output: Send {
+    fn play(&mut self, source: BoxedSource);
+    fn stop(&mut self);
+    fn pause(&mut self);
+    fn resume(&mut self);
+    fn is_paused(&self) -> bool;
+    fn set_volume(&mut self, volume: f32);
+    /// nothing is left to play, the current source ended or was stopped.
+    fn is_empty(&self) -> bool;
+
+    /// moves to another device if the one played on disappeared, true when it did.
+    /// what played before is stopped then, it has to be played again.
+    fn check_device(&mut self) -> bool {
+        false
+    }
+
+    /// name of the device played on instead of the chosen one, once after it disappeared.
+    fn take_fallback(&mut self) -> Option<String> {
+        None
+    }
+}
+
+/// Opens `device` (the default one), `device:<name>`, `null` or `wav:<file>`, see `OUTPUTS`.
+pub fn open(spec: &str) -> Result<Box<dyn Output>, Error> {
+    let output: Box<dyn Output> = match spec {
+        "device" => Box::new(DeviceOutput::open(None)?),
+        "null" => Box::new(NullOutput::new()),
+        _ if spec.starts_with("device:") && spec.len() > 7 => Box::new(DeviceOutput::open(Some(&spec[7..]))?),
+        _ if spec.starts_with("wav:") && spec.len() > 4 => Box::new(WavOutput::create(Path::new(&spec[4..]))?),
+        _ => bail!("unknown output \"{}\", expected one of {}", spec, OUTPUTS.join(", "))
+    };
+    Ok(output)
+}
+
+/// names of the sound cards that can play audio.
+pub fn list_devices() -> Vec<String> {
+    rodio::output_devices().map(|d| d.name()).collect()
+}
+
+fn find_device(name: &str) -> Option<Device> {
+    rodio::output_devices().find(|d| d.name() == name).filter(can_play)
+}
+
+/// rodio panics on a device without an output format, a busy or headless sound card has none.
+fn can_play(device: &Device) -> bool {
+    device.default_output_format().is_ok()
+}
+
+fn get_default_device() -> Option<Device> {
+    rodio::default_output_device().filter(can_play)
+}
+
+/// every output worth offering in a picker: default device, each device by name, and no output.
+pub fn list_outputs() -> Vec<String> {
+    let mut outputs = vec!["device".to_string()];
+    outputs.extend(list_devices().into_iter().map(|name| format!("device:{}", name)));
+    outputs.push("null".to_string());
+    outputs
+}
+
+/// `spec` as shown to users.
+pub fn get_output_label(spec: &str) -> String {
+    match spec {
+        "device" => "default device".to_string(),
+        "null" => "no output".to_string(),
+        _ if spec.starts_with("device:") => spec[7..].to_string(),
+        _ if spec.starts_with("wav:") => format!("wav file {}", &spec[4..]),
+        _ => spec.to_string()
+    }
+}
+
+
+/// Sound card, through rodio.
+pub struct DeviceOutput {
+    device: Device,
+    /// chosen device, none for the default one.
+    name: Option<String>,
+    sink: Sink,
+    volume: f32,
+    fallback: Option<String>,
+    checked_at: Instant,
+}
+
+impl DeviceOutput {
+    pub fn new(device: Device) -> DeviceOutput {
+        DeviceOutput {
+            sink: Sink::new(&device),
+            device,
+            name: None,
+            volume: 1.0,
+            fallback: None,
+            checked_at: Instant::now(),
+        }
+    }
+
+    /// device called `name`, or the default one when there is no such device.
+    pub fn open(name: Option<&str>) -> Result<DeviceOutput, Error> {
+        if let Some(device) = name.and_then(find_device) {
+            let mut output = DeviceOutput::new(device);
+            output.name = name.map(String::from);
+            return Ok(output);
+        }
+
+        let mut output = DeviceOutput::new(get_default_device().ok_or(TsiangtError::NoOutputDevice)?);
+        if name.is_some() {
+            output.fallback = Some(output.device.name());
+        }
+        Ok(output)
+    }
+
+    /// a vanished device would make rodio panic, the default one takes over instead.
+    /// true when it did.
+    fn ensure_device(&mut self) -> bool {
+        self.checked_at = Instant::now();
+        let name = self.name.clone().unwrap_or_else(|| self.device.name());
+        if list_devices().contains(&name) {
+            return false;
+        }
+        match get_default_device() {
+            Some(device) => {
+                self.fallback = Some(device.name());
+                self.device = device;
+                self.name = None;
+                true
+            },
+            None => false
+        }
+    }
+}
+
+impl Output for DeviceOutput {
+    fn play(&mut self, source: BoxedSource) {
+        self.ensure_device();
+        self.stop();
+        self.sink.append(source);
+    }
+
+    fn stop(&mut self) {
+        self.sink = Sink::new(&self.device);
+        self.sink.set_volume(self.volume);
+    }
+
+    fn pause(&mut self) {
+        self.sink.pause();
+    }
+
+    fn resume(&mut self) {
+        self.sink.play();
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        self.sink.set_volume(volume);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.sink.empty()
+    }
+
+    fn check_device(&mut self) -> bool {
+        if self.checked_at.elapsed() < DEVICE_CHECK_INTERVAL || !self.ensure_device() {
+            return false;
+        }
+        self.stop();
+        true
+    }
+
+    fn take_fallback(&mut self) -> Option<String> {
+        self.fallback.take()
+    }
+}
+
+
+/// Discards samples, as fast as a device would play them.
+pub struct NullOutput {
+    clock: Clock,
+}
+
+impl NullOutput {
+    pub fn new() -> NullOutput {
+        NullOutput { clock: Clock::new() }
+    }
+}
+
+impl Default for NullOutput {
+    fn default() -> NullOutput {
+        NullOutput::new()
+    }
+}
+
+impl Output for NullOutput {
+    fn play(&mut self, source: BoxedSource) {
+        self.clock.play(source, |_| {});
+    }
+
+    fn stop(&mut self) { self.clock.stop() }
+    fn pause(&mut self) { self.clock.set_paused(true) }
+    fn resume(&mut self) { self.clock.set_paused(false) }
+    fn is_paused(&self) -> bool { self.clock.is_paused }
+    fn set_volume(&mut self, volume: f32) { self.clock.set_volume(volume) }
+    fn is_empty(&self) -> bool { self.clock.is_empty() }
+}
+
+
+/// Records everything played into one wav file (16 bit, stereo, 44.1 kHz), in real time.
+pub struct WavOutput {
+    clock: Clock,
+    writer: Arc<Mutex<WavWriter<BufWriter<File>>>>,
+}
+
+impl WavOutput {
+    pub fn create(path: &Path) -> Result<WavOutput, Error> {
+        let spec = WavSpec {
+            channels: WAV_CHANNELS,
+            sample_rate: WAV_SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let writer = WavWriter::create(path, spec)
+            .map_err(|e| format_err!("cannot create {}: {}", path.display(), e))?;
+        Ok(WavOutput {
+            clock: Clock::new(),
+            writer: Arc::new(Mutex::new(writer)),
+        })
+    }
+
+    /// updates the header, so the file is valid even if tsiangt never finishes it.
+    fn flush(&self) {
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writer.flush();
+        }
+    }
+}
+
+impl Output for WavOutput {
+    fn play(&mut self, source: BoxedSource) {
+        let source: UniformSourceIterator<BoxedSource, i16> = UniformSourceIterator::new(source, WAV_CHANNELS, WAV_SAMPLE_RATE);
+        let writer = self.writer.clone();
+        let mut chunks = 0;
+        self.clock.play(Box::new(source), move |samples| {
+            if let Ok(mut writer) = writer.lock() {
+                for sample in samples {
+                    let _ = writer.write_sample(*sample);
+                }
+                chunks += 1;
+                if chunks % WAV_FLUSH_CHUNKS == 0 {
+                    let _ = writer.flush();
+                }
+            }
+        });
+    }
+
+    fn stop(&mut self) {
+        self.clock.stop();
+        self.flush();
+    }
+
+    fn pause(&mut self) { self.clock.set_paused(true) }
+    fn resume(&mut self) { self.clock.set_paused(false) }
+    fn is_paused(&self) -> bool { self.clock.is_paused }
+    fn set_volume(&mut self, volume: f32) { self.clock.set_volume(volume) }
+    fn is_empty(&self) -> bool { self.clock.is_empty() }
+}
+
+impl Drop for WavOutput {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+
+/// state shared with the thread consuming one source.
+#[derive(Default)]
+struct Playback {
+    is_paused: AtomicBool,
+    is_stopped: AtomicBool,
+    is_done: AtomicBool,
+}
+
+/// Consumes sources at their sample rate on a thread, for outputs without a device to pace them.
+struct Clock {
+    playback: Option<Arc<Playback>>,
+    is_paused: bool,
+    /// `f32` bits.
+    volume: Arc<AtomicU32>,
+}
+
+impl Clock {
+    fn new() -> Clock {
+        Clock {
+            playback: None,
+            is_paused: false,
+            volume: Arc::new(AtomicU32::new(1.0f32.to_bits())),
+        }
+    }
+
+    fn play<F>(&mut self, mut source: BoxedSource, mut write: F)
+        where F: FnMut(&[i16]) + Send + 'static
+    {
+        self.stop();
+        self.is_paused = false;
+        let playback = Arc::new(Playback::default());
+        self.playback = Some(playback.clone());
+        let volume = self.volume.clone();
+
+        thread::spawn(move || {
+            let samples_per_chunk = (source.sample_rate() as usize * source.channels() as usize / 100).max(1);
+            let mut chunk = Vec::with_capacity(samples_per_chunk);
+            let mut deadline = Instant::now();
+            loop {
+                if playback.is_stopped.load(Ordering::Relaxed) {
+                    return;
+                }
+                if playback.is_paused.load(Ordering::Relaxed) {
+                    thread::sleep(CHUNK);
+                    deadline = Instant::now();
+                    continue;
+                }
+
+                let volume = f32::from_bits(volume.load(Ordering::Relaxed));
+                chunk.clear();
+                chunk.extend(source.by_ref().take(samples_per_chunk).map(|s| s.amplify(volume)));
+                write(&chunk);
+                if chunk.len() < samples_per_chunk {
+                    playback.is_done.store(true, Ordering::Relaxed);
+                    return;
+                }
+
+                deadline += CHUNK;
+                if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+            }
+        });
+    }
+
+    fn stop(&mut self) {
+        if let Some(playback) = self.playback.take() {
+            playback.is_stopped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn set_paused(&mut self, is_paused: bool) {
+        self.is_paused = is_paused;
+        if let Some(playback) = &self.playback {
+            playback.is_paused.store(is_paused, Ordering::Relaxed);
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.playback {
+            Some(p) => p.is_done.load(Ordering::Relaxed),
+            None => true
+        }
+    }
+}
+
+impl Drop for Clock {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use hound::WavReader;
+    use rodio::buffer::SamplesBuffer;
+
+    /// `frames` frames of a quiet ramp.
+    fn get_source(channels: u16, rate: u32, frames: usize) -> BoxedSource {
+        let samples: Vec<i16> = (0..frames * channels as usize).map(|i| (i % 1000) as i16).collect();
+        Box::new(SamplesBuffer::new(channels, rate, samples))
+    }
+
+    fn wait_until_empty(output: &dyn Output) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !output.is_empty() && Instant::now() < deadline {
+            thread::sleep(CHUNK);
+        }
+    }
+
+    #[test]
+    fn null_output_is_empty_after_its_source() {
+        let mut output = NullOutput::new();
+        assert!(output.is_empty());
+        output.play(get_source(2, 44100, 4410));
+        assert!(!output.is_empty());
+        wait_until_empty(&output);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn wav_output_writes_every_sample_in_its_format() {
+        let path = env::temp_dir().join(format!("tsiangt-output-{}.wav", std::process::id()));
+        {
+            let mut output = WavOutput::create(&path).unwrap();
+            output.play(get_source(2, WAV_SAMPLE_RATE, 4410));
+            wait_until_empty(&output);
+        }
+        let reader = WavReader::open(&path).unwrap();
+        let spec = reader.spec();
+        assert_eq!(spec.channels, WAV_CHANNELS);
+        assert_eq!(spec.sample_rate, WAV_SAMPLE_RATE);
+        assert_eq!(spec.bits_per_sample, 16);
+        assert_eq!(spec.sample_format, SampleFormat::Int);
+        assert_eq!(reader.len(), 4410 * 2);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn open_accepts_every_kind_of_output() {
+        assert!(open("null").is_ok());
+
+        let path = env::temp_dir().join(format!("tsiangt-open-{}.wav", std::process::id()));
+        assert!(open(&format!("wav:{}", path.display())).is_ok());
+        assert!(path.exists());
+        let _ = std::fs::remove_file(&path);
+
+        // machines without a sound card have no default device to open or fall back to.
+        for spec in &["device", "device:no such device"] {
+            if let Err(e) = open(spec) {
+                assert_eq!(e.to_string(), TsiangtError::NoOutputDevice.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn open_rejects_unknown_specs() {
+        for spec in &["", "speaker", "device:", "wav:"] {
+            assert!(open(spec).is_err(), "{:?} was accepted", spec);
+        }
+    }
+}
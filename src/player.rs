@@ -1,53 +1,325 @@
-use rodio::{Device, Sink};
-use crate::App::{ Track};
+use rodio::Source;
+use crate::track::Track;
+use crate::error::TsiangtError;
+use crate::output::{ self, BoxedSource, Output };
+use crate::equalizer::{ Equalized, SharedEq };
+use crate::replaygain::ReplayGainSettings;
+use crate::tempo::{ Stretched, SharedTempo, TempoSettings };
+use failure::Error;
 use std::fs::File;
 use std::io::BufReader;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::{ Duration, Instant };
 use crossbeam_channel::{Receiver, Sender};
 
+/// Changes of output reported to the UI.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputEvent {
+    /// now playing on this output (an `output::open` spec).
+    Switched(String),
+    /// chosen device disappeared, playing on this one instead.
+    Fallback(String),
+    /// output couldn't be opened, the previous one is kept.
+    Failed(String),
+}
+
+/// Channels `Player` is driven by, and reports back on.
+pub struct PlayerChannels {
+    pub track_rx: Receiver<Track>,
+    pub track_p_rx: Receiver<bool>,
+    pub track_i_x: Sender<bool>,
+    pub track_atp_rx: Receiver<Track>,
+    pub track_s_rx: Receiver<Duration>,
+    pub track_v_rx: Receiver<f32>,
+    /// speed and pitch to play at.
+    pub tempo_rx: Receiver<TempoSettings>,
+    /// tracks that failed to play, with the reason.
+    pub track_e_x: Sender<(Track, String)>,
+    /// outputs to switch to, see `output::open`.
+    pub output_rx: Receiver<String>,
+    pub output_x: Sender<OutputEvent>,
+}
+
 pub struct Player{
-   pub device: Device, 
-   pub handler: Sink,
-   pub track_rx: Receiver<Track>,
-   pub track_p_rx: Receiver<bool>,
-   pub track_i_x: Sender<bool>,
-   pub track_atp_rx: Receiver<Track>
+   pub output: Box<dyn Output>,
+   pub channels: PlayerChannels,
+   pub volume: f32,
+   pub replay_gain: ReplayGainSettings,
+   /// applied to every source played, shared with `App`.
+   pub equalizer: Arc<SharedEq>,
+   /// applied to every source played, see `set_tempo`.
+   pub tempo: Arc<SharedTempo>,
+   /// playing position in milliseconds, shared with `App`.
+   pub position: Arc<AtomicU64>,
+   current: Option<Track>,
+   /// `current` plays through `Stretched`, it's left out at normal speed and pitch.
+   is_stretched: bool,
+   elapsed: Duration,
+   resumed_at: Option<Instant>
 }
 
 impl Player {
-    pub fn new(
-        d: Device, 
-        track_rx: Receiver<Track>, 
-        track_p_rx: Receiver<bool>, 
-        track_i_x: Sender<bool>,
-        track_atp_rx: Receiver<Track>
-        ) -> Player {
+    pub fn new(output: Box<dyn Output>, channels: PlayerChannels) -> Player {
         Player{
-            handler: Sink::new(&d),
-            device: d,
-            track_rx,
-            track_p_rx,
-            track_i_x,
-            track_atp_rx
+            output,
+            channels,
+            volume: 1.0,
+            replay_gain: ReplayGainSettings::default(),
+            equalizer: Arc::new(SharedEq::default()),
+            tempo: Arc::new(SharedTempo::default()),
+            position: Arc::new(AtomicU64::new(0)),
+            current: None,
+            is_stretched: false,
+            elapsed: Duration::from_secs(0),
+            resumed_at: None
+        }
+    }
+
+    pub fn set_volume(&mut self, volume: f32){
+        self.volume = volume;
+        self.output.set_volume(volume);
+    }
+
+    /// an unplayable track leaves the sink empty, so playback moves on to the next one.
+    pub fn play(&mut self, track: Track) -> Result<(), Error> {
+        self.play_from(track, Duration::from_secs(0))
+    }
+
+    fn play_from(&mut self, track: Track, position: Duration) -> Result<(), Error> {
+        self.stop();
+
+        let file = File::open(&track.file_path)
+            .map_err(|e| TsiangtError::Open { path: track.file_path.clone(), cause: e })?;
+        let mut source = rodio::Decoder::new(BufReader::new(file))
+            .map_err(|e| TsiangtError::Decode { path: track.file_path.clone(), reason: e.to_string() })?;
+
+        // decoder can't seek, skip samples up to `position` instead.
+        let samples = position.as_millis() as u64
+            * u64::from(source.sample_rate())
+            * u64::from(source.channels())
+            / 1000;
+        for _ in 0..samples {
+            if source.next().is_none() {
+                break;
+            }
+        }
+
+        let source = source.amplify(self.replay_gain.get_factor(&track));
+        self.is_stretched = !self.tempo.get().is_default();
+        let source: BoxedSource = match self.is_stretched {
+            true => Box::new(Equalized::new(Stretched::new(source, self.tempo.clone()), self.equalizer.clone())),
+            false => Box::new(Equalized::new(source, self.equalizer.clone()))
+        };
+        self.output.play(source);
+        self.current = Some(track);
+        self.elapsed = position;
+        self.resumed_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// changes speed and pitch of what's playing, and of what plays next.
+    pub fn set_tempo(&mut self, tempo: TempoSettings) {
+        // position moves at the old speed up to now.
+        if self.resumed_at.is_some() {
+            self.elapsed = self.get_position();
+            self.resumed_at = Some(Instant::now());
+        }
+        let tempo = tempo.clamp();
+        self.tempo.set(tempo);
+        // a track started at normal tempo has no stretcher to follow the change.
+        if !self.is_stretched && !tempo.is_default() && !self.output.is_empty() {
+            let position = self.get_position();
+            let is_paused = self.output.is_paused();
+            self.restart_at(position, is_paused);
         }
     }
 
-    pub fn play(&mut self, track: Track){
-        self.handler = Sink::new(&self.device);
-        let file = File::open(&track.file_path).unwrap();
-        let source = rodio::Decoder::new(BufReader::new(file)).unwrap();
-        self.handler.append(source);
+    /// plays `track`, reporting it to `App` when it can't be played.
+    pub fn play_or_report(&mut self, track: Track) {
+        if let Err(e) = self.play(track.clone()) {
+            let _ = self.channels.track_e_x.send((track, e.to_string()));
+        }
     }
 
     pub fn pause(&mut self){
-        if self.handler.is_paused() {
-            self.handler.play();
+        if self.output.is_paused() {
+            self.output.resume();
+            self.resumed_at = Some(Instant::now());
         } else {
-            self.handler.pause();
+            self.output.pause();
+            self.elapsed = self.get_position();
+            self.resumed_at = None;
         }
     }
 
     pub fn stop(&mut self) {
-        self.handler = Sink::new(&self.device);
+        self.output.stop();
+        self.current = None;
+        self.elapsed = Duration::from_secs(0);
+        self.resumed_at = None;
+    }
+
+    pub fn seek(&mut self, position: Duration) {
+        let is_paused = self.output.is_paused();
+        self.restart_at(position, is_paused);
+    }
+
+    /// plays on `spec` from now on, the current track goes on where it was.
+    pub fn switch_output(&mut self, spec: &str) {
+        let new_output = match output::open(spec) {
+            Ok(o) => o,
+            Err(e) => {
+                let _ = self.channels.output_x.send(OutputEvent::Failed(e.to_string()));
+                return;
+            }
+        };
+        let position = self.get_position();
+        let is_paused = self.output.is_paused();
+        let is_playing = !self.output.is_empty();
+        self.output.stop();
+        self.output = new_output;
+        self.output.set_volume(self.volume);
+        let _ = self.channels.output_x.send(OutputEvent::Switched(spec.to_string()));
+        self.report_fallback();
+        if is_playing {
+            self.restart_at(position, is_paused);
+        }
+    }
+
+    /// goes on at the same position on the default device when the one played on disappeared.
+    pub fn check_device(&mut self) {
+        let position = self.get_position();
+        let is_paused = self.output.is_paused();
+        if self.output.check_device() {
+            self.restart_at(position, is_paused);
+        }
+    }
+
+    /// tells the UI when the chosen device disappeared.
+    pub fn report_fallback(&mut self) {
+        if let Some(name) = self.output.take_fallback() {
+            let _ = self.channels.output_x.send(OutputEvent::Fallback(name));
+        }
+    }
+
+    fn restart_at(&mut self, position: Duration, is_paused: bool) {
+        if let Some(track) = self.current.take() {
+            if let Err(e) = self.play_from(track.clone(), position) {
+                let _ = self.channels.track_e_x.send((track, e.to_string()));
+                return;
+            }
+            if is_paused {
+                self.output.pause();
+                self.resumed_at = None;
+            }
+        }
+    }
+
+    /// position in the track, which moves faster than time when sped up.
+    pub fn get_position(&self) -> Duration {
+        match self.resumed_at {
+            Some(t) => self.elapsed + t.elapsed().mul_f32(self.tempo.get().speed),
+            None => self.elapsed
+        }
+    }
+
+    pub fn update_position(&self) {
+        self.position.store(self.get_position().as_millis() as u64, Ordering::Relaxed);
     }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::thread;
+    use crossbeam_channel as channel;
+    use hound::{ SampleFormat, WavSpec, WavWriter };
+    use crate::output::NullOutput;
+    use crate::replaygain::ReplayGain;
+
+    fn get_player() -> Player {
+        let channels = PlayerChannels {
+            track_rx: channel::unbounded().1,
+            track_p_rx: channel::unbounded().1,
+            track_i_x: channel::unbounded().0,
+            track_atp_rx: channel::unbounded().1,
+            track_s_rx: channel::unbounded().1,
+            track_v_rx: channel::unbounded().1,
+            tempo_rx: channel::unbounded().1,
+            track_e_x: channel::unbounded().0,
+            output_rx: channel::unbounded().1,
+            output_x: channel::unbounded().0,
+        };
+        Player::new(Box::new(NullOutput::new()), channels)
+    }
+
+    /// wav file of `millis` ms of silence.
+    fn get_track(name: &str, millis: u32) -> Track {
+        let path = env::temp_dir().join(format!("tsiangt-{}-{}.wav", name, std::process::id()));
+        let spec = WavSpec { channels: 2, sample_rate: 44100, bits_per_sample: 16, sample_format: SampleFormat::Int };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for _ in 0..44100 * 2 * millis / 1000 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        Track {
+            file_path: path.display().to_string(),
+            title: name.to_string(),
+            artist: String::new(),
+            album_artist: String::new(),
+            album: String::new(),
+            year: 0,
+            track_num: 0,
+            disc_num: 0,
+            duration: 0,
+            replay_gain: ReplayGain::default(),
+        }
+    }
+
+    #[test]
+    fn output_is_empty_once_a_track_ended() {
+        let mut player = get_player();
+        let track = get_track("player", 100);
+        player.play(track.clone()).unwrap();
+        assert!(!player.output.is_empty());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !player.output.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(player.output.is_empty());
+        let _ = std::fs::remove_file(&track.file_path);
+    }
+
+    #[test]
+    fn stretcher_is_only_added_for_another_tempo() {
+        let mut player = get_player();
+        let track = get_track("tempo", 1000);
+        player.play(track.clone()).unwrap();
+        assert!(!player.is_stretched);
+
+        player.set_tempo(TempoSettings { speed: 1.5, pitch: 0.0 });
+        assert!(player.is_stretched);
+        assert!(!player.output.is_empty());
+
+        // going back to normal keeps the stretcher until the next track.
+        player.set_tempo(TempoSettings::default());
+        assert!(player.is_stretched);
+        player.play(track.clone()).unwrap();
+        assert!(!player.is_stretched);
+        let _ = std::fs::remove_file(&track.file_path);
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let mut player = get_player();
+        let mut track = get_track("missing", 0);
+        let _ = std::fs::remove_file(&track.file_path);
+        track.file_path.push_str(".gone");
+        assert!(player.play(track).is_err());
+        assert!(player.output.is_empty());
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs;
+use std::io::Write;
+use std::path::{ Path, PathBuf };
+
+use failure::Error;
+
+use crate::track::Track;
+
+
+const PLAYLIST_DIR: &str = "tsiangt/playlists";
+const PLAYLIST_EXTENSION: &str = "m3u";
+/// playlist of the last run, saved on exit and loaded on start.
+const SESSION_FILE: &str = "tsiangt/session.m3u";
+
+
+/// Saved playlists live in `~/.local/share/tsiangt/playlists/<name>.m3u`.
+pub fn get_playlist_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join(PLAYLIST_DIR))
+}
+
+pub fn get_session_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join(SESSION_FILE))
+}
+
+/// `name` is either a playlist name or a path to an m3u file.
+pub fn get_playlist_path(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+    get_playlist_dir().map(|d| d.join(format!("{}.{}", name, PLAYLIST_EXTENSION)))
+}
+
+pub fn list_playlists() -> Vec<String> {
+    let mut names = vec![];
+    if let Some(Ok(entries)) = get_playlist_dir().map(fs::read_dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) == Some(PLAYLIST_EXTENSION) {
+                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                    names.push(stem.to_string());
+                }
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+pub fn save_m3u(path: &Path, tracks: &[Track]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut file = fs::File::create(path)?;
+    writeln!(file, "#EXTM3U")?;
+    for track in tracks {
+        writeln!(file, "#EXTINF:{},{} - {}", track.duration / 1000, track.artist, track.title)?;
+        writeln!(file, "{}", track.file_path)?;
+    }
+    Ok(())
+}
+
+/// Unreadable entries are skipped, relative entries are resolved against the playlist location.
+pub fn load_m3u(path: &Path) -> Result<Vec<Track>, Error> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format_err!("cannot read playlist {}: {}", path.display(), e))?;
+    let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let tracks = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            let p = PathBuf::from(l);
+            Track::new(if p.is_absolute() { p } else { base.join(p) }).ok()
+        })
+        .collect();
+    Ok(tracks)
+}
@@ -0,0 +1,200 @@
+use id3::Tag;
+use serde::{ Deserialize, Serialize };
+
+use crate::track::Track;
+
+
+/// loudness R128 gains are relative to, ReplayGain ones to -18 LUFS.
+const R128_TO_REPLAYGAIN_DB: f32 = 5.0;
+
+/// Which gain levels tracks.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGainMode {
+    Off,
+    /// every track at the same loudness.
+    Track,
+    /// albums at the same loudness, keeping differences between their tracks.
+    Album,
+}
+
+/// Gains (dB) and peaks (1.0 = full scale) read from `REPLAYGAIN_*` or `R128_*` id3 frames.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+/// How `Player` applies `ReplayGain` of the tracks it plays.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGainSettings {
+    pub mode: ReplayGainMode,
+    /// dB added to every gain.
+    pub preamp: f32,
+    /// lowers the gain when the peak would clip.
+    pub prevent_clipping: bool,
+}
+
+impl ReplayGainMode {
+    pub fn get_title(self) -> &'static str {
+        match self {
+            ReplayGainMode::Off => "off",
+            ReplayGainMode::Track => "track",
+            ReplayGainMode::Album => "album",
+        }
+    }
+}
+
+impl ReplayGain {
+    /// id3 `TXXX` frames, `REPLAYGAIN_*` win over `R128_*`.
+    /// Vorbis comments of flac and ogg files aren't read, measured loudness stands in for them.
+    pub fn from_tag(tag: &Tag) -> ReplayGain {
+        let get = |name: &str| tag.extended_texts()
+            .find(|t| t.description.eq_ignore_ascii_case(name))
+            .map(|t| t.value.as_str());
+
+        let get_gain = |name: &str, r128_name: &str| get(name)
+            .and_then(parse_gain)
+            .or_else(|| get(r128_name).and_then(parse_r128_gain));
+        let get_peak = |name: &str| get(name).and_then(|v| v.trim().parse::<f32>().ok());
+
+        ReplayGain {
+            track_gain: get_gain("REPLAYGAIN_TRACK_GAIN", "R128_TRACK_GAIN"),
+            track_peak: get_peak("REPLAYGAIN_TRACK_PEAK"),
+            album_gain: get_gain("REPLAYGAIN_ALBUM_GAIN", "R128_ALBUM_GAIN"),
+            album_peak: get_peak("REPLAYGAIN_ALBUM_PEAK"),
+        }
+    }
+
+    /// no gain tag at all.
+    pub fn is_empty(&self) -> bool {
+        self.track_gain.is_none() && self.album_gain.is_none()
+    }
+
+    /// gain and peak for `mode`, falling back to the other kind when it's missing.
+    pub fn get(&self, mode: ReplayGainMode) -> Option<(f32, Option<f32>)> {
+        let track = self.track_gain.map(|g| (g, self.track_peak));
+        let album = self.album_gain.map(|g| (g, self.album_peak));
+        match mode {
+            ReplayGainMode::Off => None,
+            ReplayGainMode::Track => track.or(album),
+            ReplayGainMode::Album => album.or(track),
+        }
+    }
+}
+
+impl Default for ReplayGainSettings {
+    fn default() -> ReplayGainSettings {
+        ReplayGainSettings {
+            mode: ReplayGainMode::Off,
+            preamp: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+impl ReplayGainSettings {
+    /// dB applied to `track`, none when it has no gain tags or leveling is off.
+    pub fn get_gain(&self, track: &Track) -> Option<f32> {
+        let (gain, peak) = track.replay_gain.get(self.mode)?;
+        let mut gain = gain + self.preamp;
+        if let (true, Some(peak)) = (self.prevent_clipping, peak) {
+            if peak > 0.0 {
+                gain = gain.min(-20.0 * peak.log10());
+            }
+        }
+        Some(gain)
+    }
+
+    /// amplitude factor applied to samples of `track`.
+    pub fn get_factor(&self, track: &Track) -> f32 {
+        match self.get_gain(track) {
+            Some(gain) => 10f32.powf(gain / 20.0),
+            None => 1.0
+        }
+    }
+}
+
+/// "-6.50 dB", "+1.2 dB" or "3".
+fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = match value.len().checked_sub(2) {
+        Some(i) if value.is_char_boundary(i) && value[i..].eq_ignore_ascii_case("db") => &value[..i],
+        _ => value
+    };
+    value.trim().trim_start_matches('+').parse().ok()
+}
+
+/// Q7.8 fixed point dB relative to -23 LUFS.
+fn parse_r128_gain(value: &str) -> Option<f32> {
+    value.trim().parse::<i16>().ok().map(|v| f32::from(v) / 256.0 + R128_TO_REPLAYGAIN_DB)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::Track;
+
+    fn get_track(replay_gain: ReplayGain) -> Track {
+        Track {
+            file_path: String::new(),
+            title: String::new(),
+            artist: String::new(),
+            album_artist: String::new(),
+            album: String::new(),
+            year: 0,
+            track_num: 0,
+            disc_num: 0,
+            duration: 0,
+            replay_gain,
+        }
+    }
+
+    #[test]
+    fn parse_gain_reads_signed_db() {
+        assert_eq!(parse_gain("-6.50 dB"), Some(-6.5));
+        assert_eq!(parse_gain(" +3 dB"), Some(3.0));
+        assert_eq!(parse_gain("1.25db"), Some(1.25));
+        assert_eq!(parse_gain("3"), Some(3.0));
+        assert_eq!(parse_gain("loud"), None);
+        assert_eq!(parse_gain("dB"), None);
+        assert_eq!(parse_gain("ÿdB"), None);
+    }
+
+    #[test]
+    fn parse_r128_gain_reads_q7_8_relative_to_replaygain() {
+        assert_eq!(parse_r128_gain("0"), Some(5.0));
+        assert_eq!(parse_r128_gain("-1536"), Some(-1.0));
+        assert_eq!(parse_r128_gain(" 128 "), Some(5.5));
+        assert_eq!(parse_r128_gain("-6.5 dB"), None);
+        assert_eq!(parse_r128_gain("40000"), None);
+    }
+
+    #[test]
+    fn get_gain_adds_preamp() {
+        let track = get_track(ReplayGain { track_gain: Some(-6.0), album_gain: Some(-8.0), ..ReplayGain::default() });
+        let settings = ReplayGainSettings { mode: ReplayGainMode::Track, preamp: 2.0, prevent_clipping: true };
+        assert_eq!(settings.get_gain(&track), Some(-4.0));
+        let settings = ReplayGainSettings { mode: ReplayGainMode::Album, ..settings };
+        assert_eq!(settings.get_gain(&track), Some(-6.0));
+        let settings = ReplayGainSettings { mode: ReplayGainMode::Off, ..settings };
+        assert_eq!(settings.get_gain(&track), None);
+        assert_eq!(settings.get_factor(&track), 1.0);
+    }
+
+    #[test]
+    fn get_gain_stops_the_peak_from_clipping() {
+        // a peak of 0.5 leaves about 6 dB.
+        let track = get_track(ReplayGain { track_gain: Some(9.0), track_peak: Some(0.5), ..ReplayGain::default() });
+        let settings = ReplayGainSettings { mode: ReplayGainMode::Track, preamp: 0.0, prevent_clipping: true };
+        let gain = settings.get_gain(&track).unwrap();
+        assert!((gain - 6.0206).abs() < 1e-3, "{}", gain);
+        assert!((settings.get_factor(&track) * 0.5 - 1.0).abs() < 1e-4);
+
+        let settings = ReplayGainSettings { prevent_clipping: false, ..settings };
+        assert_eq!(settings.get_gain(&track), Some(9.0));
+    }
+}
@@ -0,0 +1,378 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::Source;
+
+use crate::dsp::Shared;
+
+
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 3.0;
+/// pitch shift is limited to +/- this many semitones.
+pub const MAX_PITCH: f32 = 12.0;
+
+/// segments overlapped by WSOLA, and how far (both ms) a segment may move to match the previous one.
+const SEGMENT_MS: u32 = 40;
+const SEARCH_MS: u32 = 10;
+/// the search first tries every `COARSE_STEP`th offset on every `COARSE_STEP / 2`th sample, then refines.
+const COARSE_STEP: usize = 8;
+
+
+/// Playback speed and pitch, changed while playing through `SharedTempo`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoSettings {
+    /// 1.0 is normal speed, pitch stays the same at any speed.
+    pub speed: f32,
+    /// semitones, 0.0 keeps the pitch.
+    pub pitch: f32,
+}
+
+pub type SharedTempo = Shared<TempoSettings>;
+
+impl Default for TempoSettings {
+    fn default() -> TempoSettings {
+        TempoSettings { speed: 1.0, pitch: 0.0 }
+    }
+}
+
+impl TempoSettings {
+    pub fn is_default(&self) -> bool {
+        *self == TempoSettings::default()
+    }
+
+    /// `speed` and `pitch` within their limits.
+    pub fn clamp(self) -> TempoSettings {
+        TempoSettings {
+            speed: self.speed.clamp(MIN_SPEED, MAX_SPEED),
+            pitch: self.pitch.clamp(-MAX_PITCH, MAX_PITCH),
+        }
+    }
+
+    /// frequency ratio of `pitch`.
+    pub fn get_pitch_ratio(&self) -> f32 {
+        2f32.powf(self.pitch / 12.0)
+    }
+}
+
+
+/// Source played at another speed and pitch.
+///
+/// Time is stretched by WSOLA (overlapping segments picked where they best match what was
+/// played before) to `speed / pitch ratio`, then resampled by the pitch ratio, which brings
+/// the speed back to `speed` and moves the pitch.
+pub struct Stretched<S> {
+    source: S,
+    shared: Arc<SharedTempo>,
+    /// version of `shared` in use.
+    version: Option<usize>,
+    channels: usize,
+    /// input frames consumed per output frame of WSOLA.
+    tempo: f32,
+    /// stretched frames consumed per output frame of the resampler.
+    ratio: f32,
+    /// frames of a segment, segments overlap by half.
+    segment: usize,
+    search: usize,
+    window: Vec<f32>,
+    /// interleaved samples not needed by WSOLA yet.
+    input: Vec<f32>,
+    /// frames of `input` that came from the source, the rest is padding after its end.
+    input_end: Option<usize>,
+    /// frame of `input` where the next segment would start at this tempo.
+    position: f32,
+    /// frame of `input` that followed the previous segment, what the next one should sound like.
+    natural: Option<usize>,
+    /// second half of the previous segment, windowed, added to the next one.
+    tail: Vec<f32>,
+    /// output of WSOLA, waiting for the resampler.
+    stretched: Vec<f32>,
+    /// frame of `stretched` the resampler is at.
+    resample_position: f32,
+    ready: VecDeque<i16>,
+}
+
+impl<S> Stretched<S>
+    where S: Source<Item = i16>
+{
+    pub fn new(source: S, shared: Arc<SharedTempo>) -> Stretched<S> {
+        let channels = usize::from(source.channels().max(1));
+        let rate = source.sample_rate();
+        // even, so the halves of a segment are equal.
+        let segment = ((rate * SEGMENT_MS / 1000) as usize / 2 * 2).max(2 * COARSE_STEP);
+        // periodic hann, the halves of two overlapping segments add up to 1.
+        let window = (0..segment)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / segment as f32).cos())
+            .collect();
+        Stretched {
+            source,
+            shared,
+            version: None,
+            channels,
+            tempo: 1.0,
+            ratio: 1.0,
+            segment,
+            search: (rate * SEARCH_MS / 1000) as usize,
+            window,
+            input: Vec::new(),
+            input_end: None,
+            position: 0.0,
+            natural: None,
+            tail: Vec::new(),
+            stretched: Vec::new(),
+            resample_position: 0.0,
+            ready: VecDeque::new(),
+        }
+    }
+
+    fn update(&mut self) {
+        let version = self.shared.get_version();
+        if self.version == Some(version) {
+            return;
+        }
+        self.version = Some(version);
+        let settings = self.shared.get().clamp();
+        self.ratio = settings.get_pitch_ratio();
+        self.tempo = settings.speed / self.ratio;
+    }
+
+    /// reads the source until `input` holds `frames` frames, padding with silence after its end.
+    fn read_input(&mut self, frames: usize) {
+        let samples = frames * self.channels;
+        while self.input.len() < samples {
+            match self.input_end {
+                Some(_) => self.input.push(0.0),
+                None => match self.source.next() {
+                    Some(s) => self.input.push(f32::from(s)),
+                    // a partial frame is dropped.
+                    None => {
+                        self.input.truncate(self.input.len() / self.channels * self.channels);
+                        self.input_end = Some(self.input.len() / self.channels);
+                    }
+                }
+            }
+        }
+    }
+
+    /// overlaps one more segment, false once the source is over.
+    fn stretch(&mut self) -> bool {
+        let c = self.channels;
+        let hop = self.segment / 2;
+        let nominal = self.position.round() as usize;
+
+        if let Some(end) = self.input_end {
+            if nominal >= end {
+                // what's left of the last segment, then nothing.
+                if self.tail.is_empty() {
+                    return false;
+                }
+                self.stretched.append(&mut self.tail);
+                return true;
+            }
+        }
+
+        let start = match self.natural {
+            // at normal tempo segments just follow each other, so nothing changes.
+            Some(natural) if self.tempo == 1.0 => natural,
+            Some(natural) => {
+                self.read_input(nominal.max(natural) + self.search + self.segment);
+                self.find_best_start(nominal, natural)
+            },
+            None => nominal
+        };
+        self.read_input(start + self.segment);
+
+        // padding after the end of the source isn't played.
+        let len = match self.input_end {
+            Some(end) => end.saturating_sub(start).min(self.segment),
+            None => self.segment
+        };
+        let segment = &self.input[start * c..(start + self.segment) * c];
+        let is_first = self.tail.is_empty();
+        for (i, &x) in segment[..hop.min(len) * c].iter().enumerate() {
+            // the first segment has nothing to fade in from.
+            let y = if is_first { x } else { self.tail[i] + x * self.window[i / c] };
+            self.stretched.push(y);
+        }
+        if len <= hop {
+            // the source ends within this half, nothing is left to overlap.
+            self.tail.clear();
+            self.input.clear();
+            self.input_end = Some(0);
+            self.position = 0.0;
+            return true;
+        }
+        self.tail = (hop * c..self.segment * c).map(|i| segment[i] * self.window[i / c]).collect();
+
+        self.position += hop as f32 * self.tempo;
+
+        // input before both candidates of the next segment isn't needed anymore.
+        let consumed = (self.position as usize).saturating_sub(self.search).min(start + hop);
+        self.input.drain(..consumed * c);
+        self.position -= consumed as f32;
+        self.natural = Some(start + hop - consumed);
+        self.input_end = self.input_end.map(|e| e.saturating_sub(consumed));
+        true
+    }
+
+    /// start near `nominal` whose beginning best matches the frames at `natural`.
+    fn find_best_start(&self, nominal: usize, natural: usize) -> usize {
+        let from = nominal.saturating_sub(self.search);
+        let to = nominal + self.search;
+        let overlap = self.segment / 2;
+
+        let best_of = |starts: &mut dyn Iterator<Item = usize>, sample_step: usize| starts
+            .map(|start| (start, self.get_similarity(start, natural, overlap, sample_step)))
+            .fold((from, f32::MIN), |best, s| if s.1 > best.1 { s } else { best })
+            .0;
+        let coarse = best_of(&mut (from..=to).step_by(COARSE_STEP), COARSE_STEP / 2);
+        let refine_from = coarse.saturating_sub(COARSE_STEP - 1).max(from);
+        let refine_to = (coarse + COARSE_STEP - 1).min(to);
+        best_of(&mut (refine_from..=refine_to), 1)
+    }
+
+    /// normalized cross-correlation of `len` frames at `a` and `b`, channels mixed down.
+    fn get_similarity(&self, a: usize, b: usize, len: usize, step: usize) -> f32 {
+        let c = self.channels;
+        let mono = |frame: usize| self.input[frame * c..(frame + 1) * c].iter().sum::<f32>();
+        let mut correlation = 0.0;
+        let mut energy = 0.0;
+        for i in (0..len).step_by(step) {
+            let x = mono(a + i);
+            correlation += x * mono(b + i);
+            energy += x * x;
+        }
+        correlation / (energy.sqrt() + 1.0)
+    }
+
+    /// turns stretched frames into output samples, interpolating between frames.
+    /// the last frame waits for the one after it, unless `is_done`.
+    fn resample(&mut self, is_done: bool) {
+        let c = self.channels;
+        let frames = self.stretched.len() / c;
+        let last = if is_done { frames } else { frames.saturating_sub(1) };
+        while (self.resample_position as usize) < last {
+            let i = self.resample_position as usize;
+            let f = self.resample_position - i as f32;
+            for ch in 0..c {
+                let x0 = self.stretched[i * c + ch];
+                let x1 = self.stretched.get((i + 1) * c + ch).cloned().unwrap_or(x0);
+                let y = x0 + (x1 - x0) * f;
+                self.ready.push_back(y.round().clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16);
+            }
+            self.resample_position += self.ratio;
+        }
+        let consumed = (self.resample_position as usize).min(frames);
+        self.stretched.drain(..consumed * c);
+        self.resample_position -= consumed as f32;
+    }
+}
+
+impl<S> Iterator for Stretched<S>
+    where S: Source<Item = i16>
+{
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        loop {
+            if let Some(sample) = self.ready.pop_front() {
+                return Some(sample);
+            }
+            self.update();
+            self.resample(false);
+            if self.ready.is_empty() && !self.stretch() {
+                self.resample(true);
+                return self.ready.pop_front();
+            }
+        }
+    }
+}
+
+impl<S> Source for Stretched<S>
+    where S: Source<Item = i16>
+{
+    /// format changes of the source aren't followed, it's read well ahead.
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    /// unknown, it depends on how the speed changes.
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    const RATE: u32 = 44100;
+
+    /// one second of a mono sine at `freq`.
+    fn get_sine(freq: f32) -> Vec<i16> {
+        (0..RATE)
+            .map(|i| (8000.0 * (2.0 * PI * freq * i as f32 / RATE as f32).sin()) as i16)
+            .collect()
+    }
+
+    fn stretch(samples: &[i16], speed: f32, pitch: f32) -> Vec<i16> {
+        stretch_channels(1, samples, speed, pitch)
+    }
+
+    fn stretch_channels(channels: u16, samples: &[i16], speed: f32, pitch: f32) -> Vec<i16> {
+        let shared = Arc::new(SharedTempo::default());
+        shared.set(TempoSettings { speed, pitch });
+        Stretched::new(SamplesBuffer::new(channels, RATE, samples.to_vec()), shared).collect()
+    }
+
+    fn count_zero_crossings(samples: &[i16]) -> usize {
+        samples.windows(2).filter(|w| (w[0] < 0) != (w[1] < 0)).count()
+    }
+
+    #[test]
+    fn normal_tempo_keeps_samples() {
+        let input = get_sine(440.0);
+        assert_eq!(stretch(&input, 1.0, 0.0), input);
+        assert_eq!(stretch_channels(2, &input, 1.0, 0.0), input);
+        // shorter than half a segment.
+        assert_eq!(stretch(&input[..100], 1.0, 0.0), &input[..100]);
+    }
+
+    #[test]
+    fn length_follows_speed() {
+        let input = get_sine(440.0);
+        for speed in &[0.5, 0.8, 1.5, 2.0, 3.0] {
+            let expected = input.len() as f32 / speed;
+            let len = stretch(&input, *speed, 0.0).len() as f32;
+            // at most a segment more, the last one is played out.
+            assert!((len - expected).abs() < (RATE * SEGMENT_MS / 1000) as f32, "{}x: {} of {}", speed, len, expected);
+        }
+    }
+
+    #[test]
+    fn octave_up_doubles_the_frequency() {
+        let input = get_sine(440.0);
+        let output = stretch(&input, 1.0, 12.0);
+        // same duration, so twice the crossings.
+        let ratio = count_zero_crossings(&output) as f32 / count_zero_crossings(&input) as f32;
+        assert!((ratio - 2.0).abs() < 0.05, "{}", ratio);
+    }
+
+    #[test]
+    fn clamp_keeps_settings_within_limits() {
+        let settings = TempoSettings { speed: 10.0, pitch: -20.0 }.clamp();
+        assert_eq!(settings, TempoSettings { speed: MAX_SPEED, pitch: -MAX_PITCH });
+        assert!(TempoSettings::default().clamp().is_default());
+    }
+}
@@ -0,0 +1,99 @@
+use std::io::{ self, Write };
+use std::mem;
+use std::panic;
+use std::process;
+use std::sync::{ Mutex, OnceLock };
+
+use termion::{ cursor, screen };
+use tui::Terminal;
+use tui::backend::Backend;
+
+
+/// same sequences `MouseTerminal` writes, termion keeps them private.
+const ENTER_MOUSE_SEQUENCE: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+const EXIT_MOUSE_SEQUENCE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+/// terminal mode from before raw mode, restored on panic and suspend.
+static ORIGINAL_MODE: OnceLock<libc::termios> = OnceLock::new();
+
+/// run by the panic hook before exiting, see `on_panic`.
+static PANIC_CLEANUPS: Mutex<Vec<Box<dyn Fn() + Send>>> = Mutex::new(Vec::new());
+
+
+/// must run before the terminal is put in raw mode.
+pub fn save_mode() -> io::Result<()> {
+    let mut termios: libc::termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDOUT_FILENO, &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let _ = ORIGINAL_MODE.set(termios);
+    Ok(())
+}
+
+/// leaves raw mode, mouse reporting and alternate screen, as if tsiangt had exited.
+pub fn restore() {
+    let mut stdout = io::stdout();
+    let _ = write!(stdout, "{}{}{}", EXIT_MOUSE_SEQUENCE, screen::ToMainScreen, cursor::Show);
+    let _ = stdout.flush();
+    if let Some(termios) = ORIGINAL_MODE.get() {
+        unsafe { libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, termios) };
+    }
+}
+
+/// takes the terminal back after `restore`.
+pub fn reenter() {
+    if let Some(termios) = ORIGINAL_MODE.get() {
+        let mut raw = *termios;
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, &raw);
+        }
+    }
+    let mut stdout = io::stdout();
+    let _ = write!(stdout, "{}{}{}", screen::ToAlternateScreen, ENTER_MOUSE_SEQUENCE, cursor::Hide);
+    let _ = stdout.flush();
+}
+
+/// stops the process like ctrl-z in a shell, returns once it's continued (`fg`).
+pub fn suspend<B>(terminal: &mut Terminal<B>) -> io::Result<()>
+    where B: Backend
+{
+    restore();
+    unsafe { libc::raise(libc::SIGSTOP) };
+    reenter();
+    redraw(terminal)
+}
+
+/// forgets what was drawn, so the next draw repaints every cell.
+pub fn redraw<B>(terminal: &mut Terminal<B>) -> io::Result<()>
+    where B: Backend
+{
+    let size = terminal.size()?;
+    terminal.resize(size)
+}
+
+/// `cleanup` runs when tsiangt panics, it can't rely on `App` or the player still working.
+pub fn on_panic<F>(cleanup: F)
+    where F: Fn() + Send + 'static
+{
+    if let Ok(mut cleanups) = PANIC_CLEANUPS.lock() {
+        cleanups.push(Box::new(cleanup));
+    }
+}
+
+/// restores the terminal and runs `on_panic` cleanups before the panic message is printed,
+/// then exits: a panicked player thread would otherwise leave the UI drawing over the shell.
+pub fn set_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore();
+        // a panic while registering holds the lock, cleanups are skipped then.
+        if let Ok(cleanups) = PANIC_CLEANUPS.try_lock() {
+            for cleanup in cleanups.iter() {
+                cleanup();
+            }
+        }
+        default_hook(info);
+        process::exit(101);
+    }));
+}
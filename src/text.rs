@@ -0,0 +1,158 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+
+pub const ELLIPSIS: &str = "…";
+/// space between the end and the start of a scrolling text.
+const MARQUEE_GAP: &str = "   ";
+
+
+/// terminal columns taken by `s`: wide glyphs (CJK, emoji) take two,
+/// combining characters (Thai vowels and tone marks, accents) take none.
+pub fn get_width(s: &str) -> usize {
+    UnicodeWidthStr::width(s)
+}
+
+/// cuts `s` to at most `width` columns, ending with an ellipsis when something was cut.
+/// graphemes are never split, so combining characters stay with their base.
+pub fn truncate(s: &str, width: usize) -> String {
+    if get_width(s) <= width {
+        return s.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let mut result = String::new();
+    let mut used = 0;
+    for g in s.graphemes(true) {
+        let w = get_width(g);
+        if used + w > width - 1 {
+            break;
+        }
+        result.push_str(g);
+        used += w;
+    }
+    result.push_str(ELLIPSIS);
+    result
+}
+
+/// `width` columns of `s` scrolled left by `step` columns, wrapping around.
+/// text fitting in `width` is returned as is.
+pub fn marquee(s: &str, width: usize, step: usize) -> String {
+    let text_width = get_width(s);
+    if text_width <= width {
+        return s.to_string();
+    }
+
+    let cycle = text_width + get_width(MARQUEE_GAP);
+    let looped = format!("{}{}{}", s, MARQUEE_GAP, s);
+    let mut skip = step % cycle;
+    let mut result = String::new();
+    let mut used = 0;
+    for g in looped.graphemes(true) {
+        let w = get_width(g);
+        if skip > 0 {
+            // wide glyph cut in half at the left edge.
+            if w > skip {
+                result.push_str(&" ".repeat(w - skip));
+                used += w - skip;
+            }
+            skip = skip.saturating_sub(w);
+            continue;
+        }
+        if used + w > width {
+            break;
+        }
+        result.push_str(g);
+        used += w;
+    }
+    result
+}
+
+/// cuts `s` to at most `width` columns from the start, keeping its end.
+pub fn truncate_start(s: &str, width: usize) -> String {
+    if get_width(s) <= width {
+        return s.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let mut graphemes = vec![];
+    let mut used = 0;
+    for g in s.graphemes(true).rev() {
+        let w = get_width(g);
+        if used + w > width - 1 {
+            break;
+        }
+        graphemes.push(g);
+        used += w;
+    }
+    graphemes.push(ELLIPSIS);
+    graphemes.into_iter().rev().collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_never_splits_wide_glyphs() {
+        // every glyph takes two columns, the ellipsis one.
+        assert_eq!(truncate("東京の夜", 5), "東京…");
+        assert_eq!(truncate("東京の夜", 4), "東…");
+        assert_eq!(truncate("🎵🎶🎵", 4), "🎵…");
+        assert!(get_width(&truncate("ab東京cd", 5)) <= 5);
+        assert_eq!(truncate("東京", 4), "東京");
+    }
+
+    #[test]
+    fn truncate_keeps_combining_marks_with_their_base() {
+        // "ที่" is one column: base, vowel and tone mark.
+        let s = "ที่นี่ไทย";
+        assert_eq!(get_width(s), 5);
+        assert_eq!(truncate(s, 3), "ที่นี่…");
+        assert_eq!(truncate_start(s, 3), "…ทย");
+        assert_eq!(truncate_start(s, 4), "…ไทย");
+    }
+
+    #[test]
+    fn truncate_to_zero_or_one_column() {
+        assert_eq!(truncate("abc", 0), "");
+        assert_eq!(truncate("abc", 1), ELLIPSIS);
+        assert_eq!(truncate("東", 1), ELLIPSIS);
+        assert_eq!(truncate("", 0), "");
+        assert_eq!(truncate_start("abc", 0), "");
+        assert_eq!(truncate_start("abc", 1), ELLIPSIS);
+    }
+
+    #[test]
+    fn truncate_start_keeps_the_end() {
+        assert_eq!(truncate_start("/music/rock/album", 8), "…k/album");
+        assert_eq!(truncate_start("/音楽/東京", 5), "…東京");
+    }
+
+    #[test]
+    fn marquee_scrolls_and_wraps() {
+        assert_eq!(marquee("short", 10, 3), "short");
+        assert_eq!(marquee("abcdef", 4, 0), "abcd");
+        assert_eq!(marquee("abcdef", 4, 4), "ef  ");
+        // back at the start after text and gap.
+        assert_eq!(marquee("abcdef", 4, 9), "abcd");
+    }
+
+    #[test]
+    fn marquee_pads_a_wide_glyph_cut_at_the_left_edge() {
+        // one column into "東" leaves its right half, shown as a space.
+        assert_eq!(marquee("東京の夜", 5, 1), " 京の");
+        assert_eq!(marquee("東京の夜", 5, 2), "京の");
+        // wrapped around, the text and the gap are 11 columns.
+        assert_eq!(marquee("東京の夜", 5, 12), " 京の");
+        assert_eq!(marquee("東京の夜", 5, 10), " 東京");
+        for step in 0..20 {
+            assert!(get_width(&marquee("東京の夜", 5, step)) <= 5);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::BTreeMap;
+
+use failure::Error;
+use serde::{ Deserialize, Serialize };
+use tui::style::{ Color, Modifier, Style };
+
+
+pub const THEMES: [&str; 3] = [
+    "default",
+    "monochrome",
+    "ocean"
+];
+
+
+/// Resolved styles of every themable part of the UI.
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub text: Style,
+    pub border: Style,
+    /// border and title of the focused panel, selected tab.
+    pub active: Style,
+    pub selection: Style,
+    pub playing: Style,
+    /// rows matching in-list filter.
+    pub matched: Style,
+    /// rows marked or covered by visual mode.
+    pub marked: Style,
+    pub header: Style,
+    pub status_bar: Style,
+    pub input: Style,
+    /// warning and error messages on the status bar and in the message log.
+    pub warning: Style,
+    pub error: Style,
+}
+
+/// User-defined theme (`[themes.<name>]`), unset parts are taken from `base`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub border: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playing: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matched: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub marked: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub header: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status_bar: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<StyleConfig>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<StyleConfig>,
+}
+
+/// `[colors]` of configs written before themes, foreground colors laid over the selected theme.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<String>,
+    /// borders of panels without focus.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inactive: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playing: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StyleConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fg: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bg: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<String>,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            text: Style::default().fg(Color::White),
+            border: Style::default().fg(Color::White),
+            active: Style::default().fg(Color::Green),
+            selection: Style::default().fg(Color::Gray),
+            playing: Style::default().fg(Color::Green),
+            matched: Style::default().fg(Color::Yellow),
+            marked: Style::default().fg(Color::Cyan).modifier(Modifier::BOLD),
+            header: Style::default(),
+            status_bar: Style::default().fg(Color::Black).bg(Color::Green),
+            input: Style::default().fg(Color::Yellow),
+            warning: Style::default().fg(Color::Black).bg(Color::Yellow),
+            error: Style::default().fg(Color::White).bg(Color::Red),
+        }
+    }
+}
+
+impl Theme {
+
+    /// no colors at all, only text attributes.
+    pub fn monochrome() -> Theme {
+        Theme {
+            text: Style::default(),
+            border: Style::default(),
+            active: Style::default().modifier(Modifier::BOLD),
+            selection: Style::default().modifier(Modifier::REVERSED),
+            playing: Style::default().modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            matched: Style::default().modifier(Modifier::ITALIC),
+            marked: Style::default().modifier(Modifier::UNDERLINED),
+            header: Style::default().modifier(Modifier::BOLD),
+            status_bar: Style::default().modifier(Modifier::REVERSED),
+            input: Style::default(),
+            warning: Style::default().modifier(Modifier::BOLD | Modifier::REVERSED),
+            error: Style::default().modifier(Modifier::BOLD | Modifier::REVERSED | Modifier::UNDERLINED),
+        }
+    }
+
+    pub fn ocean() -> Theme {
+        Theme {
+            text: Style::default().fg(Color::Gray),
+            border: Style::default().fg(Color::DarkGray),
+            active: Style::default().fg(Color::LightCyan),
+            selection: Style::default().fg(Color::Black).bg(Color::Cyan),
+            playing: Style::default().fg(Color::LightBlue).modifier(Modifier::BOLD),
+            matched: Style::default().fg(Color::LightYellow),
+            marked: Style::default().fg(Color::LightMagenta).modifier(Modifier::BOLD),
+            header: Style::default().fg(Color::Cyan).modifier(Modifier::BOLD),
+            status_bar: Style::default().fg(Color::White).bg(Color::Blue),
+            input: Style::default().fg(Color::LightCyan),
+            warning: Style::default().fg(Color::Black).bg(Color::LightYellow),
+            error: Style::default().fg(Color::White).bg(Color::LightRed),
+        }
+    }
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            "monochrome" => Some(Theme::monochrome()),
+            "ocean" => Some(Theme::ocean()),
+            _ => None
+        }
+    }
+
+    /// Resolves `name` among user-defined themes first, then built-in ones.
+    pub fn resolve(name: &str, themes: &BTreeMap<String, ThemeConfig>) -> Result<Theme, Error> {
+        Theme::resolve_with_depth(name, themes, 0)
+    }
+
+    fn resolve_with_depth(name: &str, themes: &BTreeMap<String, ThemeConfig>, depth: usize) -> Result<Theme, Error> {
+        if depth > themes.len() {
+            bail!("theme \"{}\" has a cyclic base", name);
+        }
+
+        let user = match themes.get(name) {
+            Some(t) => t,
+            None => return Theme::builtin(name).ok_or_else(|| {
+                format_err!("unknown theme \"{}\", expected one of {} or a [themes.{}] table", name, THEMES.join(", "), name)
+            })
+        };
+
+        let base = match &user.base {
+            Some(b) if b == name => Theme::builtin(b).ok_or_else(|| format_err!("theme \"{}\" uses itself as base", name))?,
+            Some(b) => Theme::resolve_with_depth(b, themes, depth + 1)?,
+            None => Theme::default()
+        };
+
+        Ok(Theme {
+            text: patch_style(base.text, &user.text)?,
+            border: patch_style(base.border, &user.border)?,
+            active: patch_style(base.active, &user.active)?,
+            selection: patch_style(base.selection, &user.selection)?,
+            playing: patch_style(base.playing, &user.playing)?,
+            matched: patch_style(base.matched, &user.matched)?,
+            marked: patch_style(base.marked, &user.marked)?,
+            header: patch_style(base.header, &user.header)?,
+            status_bar: patch_style(base.status_bar, &user.status_bar)?,
+            input: patch_style(base.input, &user.input)?,
+            warning: patch_style(base.warning, &user.warning)?,
+            error: patch_style(base.error, &user.error)?,
+        })
+    }
+}
+
+impl ColorsConfig {
+    pub fn apply(&self, theme: Theme) -> Result<Theme, Error> {
+        let fg = |style: Style, color: &Option<String>| -> Result<Style, Error> {
+            match color {
+                Some(c) => Ok(style.fg(parse_color(c).ok_or_else(|| format_err!("unknown color \"{}\"", c))?)),
+                None => Ok(style)
+            }
+        };
+        Ok(Theme {
+            active: fg(theme.active, &self.active)?,
+            border: fg(theme.border, &self.inactive)?,
+            selection: fg(theme.selection, &self.selection)?,
+            playing: fg(theme.playing, &self.playing)?,
+            ..theme
+        })
+    }
+}
+
+fn patch_style(style: Style, patch: &Option<StyleConfig>) -> Result<Style, Error> {
+    let patch = match patch {
+        Some(p) => p,
+        None => return Ok(style)
+    };
+
+    let mut style = style;
+    if let Some(fg) = &patch.fg {
+        style = style.fg(parse_color(fg).ok_or_else(|| format_err!("unknown color \"{}\"", fg))?);
+    }
+    if let Some(bg) = &patch.bg {
+        style = style.bg(parse_color(bg).ok_or_else(|| format_err!("unknown color \"{}\"", bg))?);
+    }
+    if !patch.modifiers.is_empty() {
+        let mut modifier = Modifier::empty();
+        for m in &patch.modifiers {
+            modifier |= parse_modifier(m).ok_or_else(|| format_err!("unknown modifier \"{}\"", m))?;
+        }
+        style = style.modifier(modifier);
+    }
+    Ok(style)
+}
+
+/// Accepts tui color names ("green", "lightblue", ..) and `#rrggbb`.
+pub fn parse_color(s: &str) -> Option<Color> {
+    let color = match s.to_lowercase().replace(['_', '-'], "").as_str() {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        // byte slicing below needs ascii.
+        hex if hex.len() == 7 && hex.starts_with('#') && hex[1..].chars().all(|c| c.is_ascii_hexdigit()) => {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            Color::Rgb(channel(1)?, channel(3)?, channel(5)?)
+        },
+        _ => return None
+    };
+    Some(color)
+}
+
+pub fn parse_modifier(s: &str) -> Option<Modifier> {
+    let modifier = match s.to_lowercase().as_str() {
+        "bold" => Modifier::BOLD,
+        "dim" => Modifier::DIM,
+        "italic" => Modifier::ITALIC,
+        "underlined" => Modifier::UNDERLINED,
+        "reversed" => Modifier::REVERSED,
+        "crossed_out" => Modifier::CROSSED_OUT,
+        _ => return None
+    };
+    Some(modifier)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_color_reads_names_and_hex() {
+        assert_eq!(parse_color("light_blue"), Some(Color::LightBlue));
+        assert_eq!(parse_color("#ff8800"), Some(Color::Rgb(255, 136, 0)));
+    }
+
+    #[test]
+    fn parse_color_rejects_non_ascii_hex() {
+        // 7 bytes, but not 7 characters.
+        assert_eq!(parse_color("#aéé1"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+    }
+}
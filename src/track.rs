@@ -0,0 +1,179 @@
+use std::cmp::Ordering;
+use std::path::{ Path, PathBuf };
+
+use failure::Error;
+use id3::Tag;
+
+use crate::error::TsiangtError;
+use crate::database;
+use crate::loudness::Measurement;
+use crate::replaygain::ReplayGain;
+
+
+#[derive(Clone, Debug)]
+pub struct Track {
+    pub file_path: String,
+    pub title: String,
+    pub artist: String,
+    pub album_artist: String,
+    pub album: String,
+    pub year: i32,
+    pub track_num: u32,
+    pub disc_num: u32,
+    pub duration: u32,
+    pub replay_gain: ReplayGain,
+}
+
+impl Track {
+
+    pub fn new(path: PathBuf) -> Result<Track, Error> {
+        //NOTE: id3 has problem reading wav file's tag;
+        let safe_tag = Tag::read_from_path(&path)
+            .map_err(|e| TsiangtError::Tag { path: path.display().to_string(), reason: e.to_string() })?;
+
+        let mut title: String = "".to_string();
+        if let Some(s) = safe_tag.title() {
+            title = s.to_string();
+        }
+
+        let mut artist: String = "".to_string();
+        if let Some(s) = safe_tag.artist() {
+            artist = s.to_string();
+        }
+
+        let mut album: String = "".to_string();
+        if let Some(s) = safe_tag.album() {
+            album = s.to_string();
+        }
+
+        let album_artist = match safe_tag.album_artist() {
+            Some(s) => s.to_string(),
+            None => artist.clone()
+        };
+
+        let mut year: i32 = 0;
+        if let Some(x) = safe_tag.year() {
+            year = x;
+        }
+
+        let mut track_num: u32 = 0;
+        if let Some(x) = safe_tag.track() {
+            track_num = x;
+        }
+
+        let mut disc_num: u32 = 0;
+        if let Some(x) = safe_tag.disc() {
+            disc_num = x;
+        }
+
+        let mut duration: u32 = 0;
+        if let Some(x) = safe_tag.duration() {
+            duration = x;
+        }
+
+        let file_path = path.as_path().to_string_lossy().to_string();
+        // measured loudness stands in for missing gain tags.
+        let mut replay_gain = ReplayGain::from_tag(&safe_tag);
+        if replay_gain.is_empty() {
+            replay_gain = database::get_replay_gain(&file_path).unwrap_or(replay_gain);
+        }
+
+        Ok(Track {
+            file_path,
+            title,
+            artist,
+            album_artist,
+            album,
+            year,
+            track_num,
+            disc_num,
+            duration,
+            replay_gain,
+        })
+    }
+}
+
+impl Track {
+    /// Formatted value of a table column: title, artist, album, year, track or duration.
+    pub fn get_column(&self, column: &str) -> String {
+        match column {
+            "title" => self.title.to_string(),
+            "artist" => self.artist.to_string(),
+            "album" => self.album.to_string(),
+            "year" => if self.year > 0 { self.year.to_string() } else { String::new() },
+            "track" => if self.track_num > 0 { self.track_num.to_string() } else { String::new() },
+            "duration" => if self.duration > 0 { format_duration(self.duration / 1000) } else { String::new() },
+            _ => String::new()
+        }
+    }
+}
+
+/// Writes one id3 frame, `field` is one of `TAG_FIELDS`.
+pub fn write_tag(path: &Path, field: &str, value: &str) -> Result<(), Error> {
+    let mut tag = Tag::read_from_path(path).unwrap_or_else(|_| Tag::new());
+    let number = || value.parse::<u32>().map_err(|_| format_err!("\"{}\" is not a number", value));
+    match field {
+        "title" => tag.set_title(value),
+        "artist" => tag.set_artist(value),
+        "album" => tag.set_album(value),
+        "album_artist" => tag.set_album_artist(value),
+        "year" => tag.set_year(number()? as i32),
+        "track" => tag.set_track(number()?),
+        _ => bail!("unknown tag \"{}\"", field)
+    }
+    tag.write_to_path(path, id3::Version::Id3v24)?;
+    Ok(())
+}
+
+/// Writes `REPLAYGAIN_*` frames from measured loudness, album ones only with `album`.
+pub fn write_replay_gain(path: &Path, track: &Measurement, album: Option<&Measurement>) -> Result<(), Error> {
+    let mut tag = Tag::read_from_path(path).unwrap_or_else(|_| Tag::new());
+    let mut set = |name: &str, value: String| {
+        tag.remove_extended_text(Some(name), None);
+        tag.add_extended_text(name, value);
+    };
+    set("REPLAYGAIN_TRACK_GAIN", format!("{:.2} dB", track.get_gain()));
+    set("REPLAYGAIN_TRACK_PEAK", format!("{:.6}", track.peak));
+    if let Some(album) = album {
+        set("REPLAYGAIN_ALBUM_GAIN", format!("{:.2} dB", album.get_gain()));
+        set("REPLAYGAIN_ALBUM_PEAK", format!("{:.6}", album.peak));
+    }
+    tag.write_to_path(path, id3::Version::Id3v24)?;
+    Ok(())
+}
+
+/// album order: album, album artist, disc, track number, then file path.
+pub fn sort_by_album(tracks: &mut [Track]) {
+    tracks.sort_by(|a, b| {
+        a.album.to_lowercase().cmp(&b.album.to_lowercase())
+            .then_with(|| a.album_artist.to_lowercase().cmp(&b.album_artist.to_lowercase()))
+            .then(a.disc_num.cmp(&b.disc_num))
+            .then(a.track_num.cmp(&b.track_num))
+            .then_with(|| a.file_path.cmp(&b.file_path))
+    });
+}
+
+impl PartialOrd for Track {
+    fn partial_cmp(&self, other: &Track) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Track {
+    fn cmp(&self, other: &Track) -> Ordering {
+        self.track_num.cmp(&other.track_num)
+    }
+}
+
+impl PartialEq for Track {
+    fn eq(&self, other: &Track) -> bool {
+        self.file_path == other.file_path
+    }
+}
+
+impl Eq for Track {}
+
+
+pub fn format_duration(seconds: u32) -> String {
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
@@ -1,17 +1,26 @@
 #[allow(dead_code)]
-use crate::App::App;
-use crate::custom_widgets::{Table as PlaylistTable, Row as PlaylistRow};
+use crate::App::{ App, Navigable, ListState };
+use tsiangt::track::{ Track, format_duration };
+use tsiangt::equalizer::{ BANDS, MAX_GAIN, get_band_label };
+use tsiangt::output::get_output_label;
+use tsiangt::replaygain::ReplayGainMode;
+use crate::custom_widgets::{Table as PlaylistTable, Row as PlaylistRow, Clear};
+use crate::keys::{ CONTEXTS, get_bindings, format_keys };
+use crate::command::COMMANDS;
+use crate::text::{ get_width, marquee, truncate, truncate_start };
+use crate::message::{ Level, format_age };
 
 use std::io;
+use std::path::Path;
 use tui::{ Terminal, Frame };
 use tui::backend::{ Backend };
-use tui::widgets::{Widget, Block, Borders, Tabs, Text, Paragraph, SelectableList};
+use tui::widgets::{Widget, Block, Borders, Tabs, Text, Paragraph, SelectableList, Gauge};
 use tui::layout::{Layout, Constraint, Direction, Alignment, Rect};
-use tui::style::{Color,  Style};
+use tui::style::{ Style };
 
 
-pub struct TableHeader<'a> {
-    text: &'a str,
+pub struct TableHeader {
+    text: String,
     width: u16,
 }
 
@@ -21,52 +30,143 @@ pub struct TableItem {
    pub format: Vec<String>,
 }
 
+/// below this width panels are stacked or collapsed into one.
+const NARROW_WIDTH: u16 = 80;
+/// narrow terminals at least this high stack panels instead of collapsing them.
+const TALL_HEIGHT: u16 = 30;
+/// smallest terminal the layout is drawn in.
+const MIN_WIDTH: u16 = 24;
+const MIN_HEIGHT: u16 = 10;
+/// columns narrower than this are hidden, least important first.
+const MIN_COLUMN_WIDTH: u16 = 12;
+/// most important first, see `config::COLUMNS`.
+const COLUMN_PRIORITY: [&str; 6] = ["title", "artist", "duration", "album", "track", "year"];
+
+/// Screen areas of the current tab, shared by drawing and mouse handling.
+#[derive(Debug, Clone)]
+pub struct AppLayout {
+    pub tabs: Rect,
+    pub search_input: Rect,
+    pub body: Rect,
+    /// one area per panel of the current tab: directory and files,
+    /// playlist and up-next queue (when not empty), or search results.
+    /// panels hidden on narrow terminals get an empty area.
+    pub panels: Vec<Rect>,
+    pub progress: Rect,
+    pub status: Rect,
+    /// library panels are collapsed into the focused one.
+    pub is_collapsed: bool,
+}
+
+pub fn get_layout(app: &App, area: Rect) -> AppLayout {
+    let is_narrow = area.width < NARROW_WIDTH;
+    let is_tall = area.height >= TALL_HEIGHT;
+
+    let chunks_main = Layout::default()
+        .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1), Constraint::Length(1)].as_ref())
+        .split(area);
+
+    // narrow tab bar keeps room for every title, the search input takes the rest.
+    let tabs_width = app.tabs.titles.iter().map(|t| get_width(t) as u16 + 3).sum::<u16>() + 2;
+    let tab_constraints = if is_narrow {
+        [Constraint::Length(tabs_width), Constraint::Min(0)]
+    } else {
+        [Constraint::Percentage(70), Constraint::Percentage(30)]
+    };
+    let chunk_tab = Layout::default()
+        .constraints(tab_constraints.as_ref())
+        .direction(Direction::Horizontal)
+        .split(chunks_main[0]);
+
+    let body = chunks_main[1];
+    let direction = if is_narrow { Direction::Vertical } else { Direction::Horizontal };
+    let is_collapsed = app.tabs.index == 1 && is_narrow && !is_tall;
+    let panels = match app.tabs.index {
+        0 if !app.queue.items.is_empty() => Layout::default()
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)].as_ref())
+            .direction(direction)
+            .split(body),
+        1 if is_collapsed => match app.tabs.panels.index {
+            0 => vec![body, Rect::default()],
+            _ => vec![Rect::default(), body]
+        },
+        1 => Layout::default()
+            .constraints([Constraint::Percentage(if is_narrow { 40 } else { 30 }), Constraint::Percentage(if is_narrow { 60 } else { 70 })].as_ref())
+            .direction(direction)
+            .split(body),
+        _ => vec![body]
+    };
+
+    AppLayout {
+        tabs: chunk_tab[0],
+        search_input: chunk_tab[1],
+        body,
+        panels,
+        progress: chunks_main[2],
+        status: chunks_main[3],
+        is_collapsed,
+    }
+}
+
 
 pub fn draw<B>(terminal: &mut Terminal<B>, app: &App)  -> Result<(), io::Error>
     where B: Backend {
 
     terminal.draw(|mut f| {
 
-        let chunks_main = Layout::default()
-            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
-            .split(f.size()); 
-
-        let chunk_tab = Layout::default()
-            .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
-            .direction(Direction::Horizontal)
-            .split(chunks_main[0]);
+        let size = f.size();
+        if size.width < MIN_WIDTH || size.height < MIN_HEIGHT {
+            Paragraph::new([Text::raw("terminal too small")].iter())
+                .style(app.theme.text)
+                .alignment(Alignment::Center)
+                .wrap(true)
+                .render(&mut f, size);
+            return;
+        }
 
-        let chunk_body = Layout::default()
-            .constraints([Constraint::Percentage(100)].as_ref())
-            .split(chunks_main[1]);
+        let layout = get_layout(app, size);
 
         Tabs::default()
             .block(Block::default().borders(Borders::ALL).title(app.title))
             .titles(&app.tabs.titles)
-            .style(Style::default())
-            .highlight_style(Style::default().fg(Color::Green))
+            .style(app.theme.text)
+            .highlight_style(app.theme.active)
             .select(app.tabs.index)
-            .render(&mut f, chunk_tab[0]);
-        draw_search_input(&mut f, &app, chunk_tab[1]);
+            .render(&mut f, layout.tabs);
+        draw_search_input(&mut f, &app, layout.search_input);
 
 
         match app.tabs.index {
-            0 => draw_playlist(&mut f, &app, chunk_body[0]),
-            1 => draw_library(&mut f, &app, chunk_body[0]),
-            2 => draw_search(&mut f, &app, chunk_body[0]),
+            0 => draw_playlist(&mut f, &app, &layout.panels),
+            1 => draw_library(&mut f, &app, &layout),
+            2 => draw_search(&mut f, &app, layout.panels[0]),
             _ => {}
         };
+
+        draw_progress(&mut f, &app, layout.progress);
+
+        if app.is_command_active {
+            draw_command_line(&mut f, &app, layout.status);
+        } else {
+            draw_status_bar(&mut f, &app, layout.status);
+        }
+
+        if app.is_help_active {
+            draw_help(&mut f, &app, layout.body);
+        } else if app.is_log_active {
+            draw_message_log(&mut f, &app, layout.body);
+        } else if app.is_output_picker_active {
+            draw_output_picker(&mut f, &app, layout.body);
+        } else if app.is_equalizer_active {
+            draw_equalizer(&mut f, &app, layout.body);
+        }
     })
   }
 
 
-fn draw_playlist<B>(f: &mut Frame<B>, app: &App, area: Rect)
+fn draw_playlist<B>(f: &mut Frame<B>, app: &App, chunks: &[Rect])
     where B: Backend {
 
-    let chunks = Layout::default()
-        .constraints([Constraint::Percentage(100)] .as_ref())
-        .split(area);
-
 
     let is_track_highlighted: bool;
 
@@ -75,19 +175,7 @@ fn draw_playlist<B>(f: &mut Frame<B>, app: &App, area: Rect)
 
        is_track_highlighted = true;
 
-       items = app
-             .playlist
-             .items
-             .iter()
-             .map(|item| TableItem {
-                 id: item.title.to_string(),
-                 format: vec![
-                     item.title.to_string().to_owned(),
-                     item.artist.to_string().to_owned(),
-                     item.album.to_string().to_owned(),
-                 ],
-             })
-             .collect::<Vec<TableItem>>();
+       items = get_visible_track_items(app, &app.playlist, chunks[0]);
     } else {
         is_track_highlighted = false;
          items.push(get_init_selection_table_state("No song added.."));
@@ -95,30 +183,70 @@ fn draw_playlist<B>(f: &mut Frame<B>, app: &App, area: Rect)
 
     let highlight_state = false;
 
-    let header = get_header(&area);
+    let is_queue_focused = app.is_queue_focused();
+    let header = get_header(app, &chunks[0]);
 
     draw_table(
         f,
         app,
         chunks[0],
         ("Playlist", &header),
-        &items,
-        app.should_select,
+        (&items, app.playlist.offset),
+        &if is_queue_focused { vec![] } else { app.get_filter_matches() },
+        &app.playlist.get_marked(),
+        app.should_select && !is_queue_focused,
         highlight_state,
         is_track_highlighted
     );
+    draw_position(f, app, chunks[0], &app.playlist);
+
+    // up-next queue panel is only shown while something is queued.
+    if let Some(area) = chunks.get(1) {
+        draw_queue(f, app, *area);
+    }
+}
+
+fn draw_queue<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend {
+
+    let is_queue_focused = app.is_queue_focused();
+    let header = get_header(app, &area);
+
+    draw_table(
+        f,
+        app,
+        area,
+        ("Up next", &header),
+        (&get_visible_track_items(app, &app.queue, area), app.queue.offset),
+        &if is_queue_focused { app.get_filter_matches() } else { vec![] },
+        &app.queue.get_marked(),
+        is_queue_focused,
+        is_queue_focused,
+        false
+    );
+    draw_position(f, app, area, &app.queue);
 }
 
-fn draw_library<B>(f: &mut Frame<B>, app: &App, area: Rect)
+fn draw_library<B>(f: &mut Frame<B>, app: &App, layout: &AppLayout)
         where B: Backend
 {
-      let chunks = Layout::default()
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)].as_ref())
-        .direction(Direction::Horizontal)
-        .split(area);
-  
-    draw_directory(f, app, chunks[0]);
-    draw_directory_files(f, app, chunks[1]);
+    let (directory, files) = (layout.panels[0], layout.panels[1]);
+
+    // a single panel shows where it is instead of its name.
+    if layout.is_collapsed {
+        let width = (layout.body.width as usize).saturating_sub(4);
+        if app.tabs.panels.index == 0 {
+            draw_directory(f, app, directory, &get_breadcrumb(app, Some(&app.current_dir), width));
+        } else {
+            let dir = app.directory_files.items.first().and_then(|t| Path::new(&t.file_path).parent());
+            draw_directory_files(f, app, files, &get_breadcrumb(app, dir, width));
+        }
+        return;
+    }
+
+    let width = (directory.width as usize).saturating_sub(4);
+    draw_directory(f, app, directory, &get_breadcrumb(app, Some(&app.current_dir), width));
+    draw_directory_files(f, app, files, app.tabs.panels.titles[1]);
 }
 
 fn draw_search<B>(f: &mut Frame<B>, app: &App, area: Rect) 
@@ -130,27 +258,52 @@ fn draw_search<B>(f: &mut Frame<B>, app: &App, area: Rect)
         .split(area);
 
      let mut items = Vec::new();
+     let has_results = app.search_results.items.len() > 0;
 
-    items.push(get_init_selection_table_state("No Result found.."));
+    if has_results {
+        items = get_visible_track_items(app, &app.search_results, chunks[0]);
+    } else {
+        items.push(get_init_selection_table_state("No Result found.."));
+    }
 
     let highlight_state = false;
 
-    let header = get_header(&area);
+    let header = get_header(app, &area);
    
     draw_table(
         f,
         app,
         chunks[0],
-        ( &app.tabs.panels.titles[1] , &header),
-        &items,
-        false,
+        ( "Results" , &header),
+        (&items, app.search_results.offset),
+        &app.get_filter_matches(),
+        &app.search_results.get_marked(),
+        has_results,
         highlight_state,
         false
     );
+    draw_position(f, app, chunks[0], &app.search_results);
 }
 
 
 
+/// `row/total` on the bottom border of a list.
+fn draw_position<B>(f: &mut Frame<B>, app: &App, area: Rect, list: &dyn Navigable)
+    where B: Backend
+{
+    if list.len() == 0 || area.height < 2 {
+        return;
+    }
+    let text = format!(" {}/{} ", list.get_selected() + 1, list.len());
+    let width = text.chars().count() as u16;
+    if width + 2 > area.width {
+        return;
+    }
+    Paragraph::new([Text::raw(text)].iter())
+        .style(app.theme.border)
+        .render(f, Rect::new(area.right() - width - 1, area.bottom() - 1, width, 1));
+}
+
 fn draw_search_input<B>(f: &mut Frame<B>, app: &App, area: Rect) 
     where B: Backend
 {
@@ -162,12 +315,12 @@ fn draw_search_input<B>(f: &mut Frame<B>, app: &App, area: Rect)
 
     // Input box
     Paragraph::new([Text::raw(&app.search_input)].iter())
-        .style(Style::default().fg(Color::Yellow))
+        .style(app.theme.input)
         .block(
         Block::default()
             .borders(Borders::ALL)
-            .style(Style::default().fg(Color::White))
-            .border_style(get_color(app.is_search_active))
+            .style(app.theme.text)
+            .border_style(get_color(app, app.is_search_active))
         )    
         .render(f, chunks[0]);
 
@@ -176,23 +329,287 @@ fn draw_search_input<B>(f: &mut Frame<B>, app: &App, area: Rect)
 }
 
 
-fn draw_directory<B>(f: &mut Frame<B>, app: &App, area: Rect)
-    where B: Backend 
+/// elapsed / total time of the playing song, clicking it seeks.
+fn draw_progress<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend
 {
-    let active = get_color(*&app.tabs.panels.index == 0);
+    let (ratio, label) = match app.get_playing_track() {
+        Some(t) if t.duration > 0 => {
+            let position = app.get_position().as_millis() as u32;
+            (
+                (f64::from(position) / f64::from(t.duration)).min(1.0),
+                format!("{} / {}", format_duration(position / 1000), format_duration(t.duration / 1000))
+            )
+        },
+        _ => (0.0, String::new())
+    };
 
-    let mut d = Vec::new();
+    Gauge::default()
+        .style(app.theme.active)
+        .ratio(ratio)
+        .label(&label)
+        .render(f, area);
+}
 
-    for directory in &app.directory.items {
-        d.push(format!("/{}", directory
-            .file_name()
-            .unwrap()
-            .to_owned()
-            .to_os_string()
-            .into_string()
-            .unwrap()
-            )
+fn draw_command_line<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend
+{
+    let completions = &app.command_line.completions;
+    if !completions.is_empty() && area.y > 0 {
+        let area = Rect::new(area.x, area.y - 1, area.width, 1);
+        Clear.render(f, area);
+        Paragraph::new([Text::raw(completions.join("  "))].iter())
+            .style(app.theme.selection)
+            .render(f, area);
+    }
+
+    Paragraph::new([Text::raw(format!(":{}_", app.command_line.input))].iter())
+        .style(app.theme.input)
+        .render(f, area);
+}
+
+fn draw_status_bar<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend
+{
+    if let Some(filter) = &app.list_filter {
+        let line = format!(" /{}{}  [{} match(es)]", filter.query, if filter.is_typing { "_" } else { "" }, app.get_filter_matches().len());
+        Paragraph::new([Text::raw(line)].iter())
+            .style(app.theme.status_bar)
+            .render(f, area);
+        return;
+    }
+
+    if let Some(message) = app.messages.get_current() {
+        Paragraph::new([Text::raw(format!(" {}", message.text))].iter())
+            .style(get_level_style(app, message.level))
+            .render(f, area);
+        return;
+    }
+
+    let state = match (app.get_playing_track(), app.is_playing) {
+        (Some(t), true) => format!(" > {} - {}", t.title, t.artist),
+        (Some(t), false) => format!(" || {} - {}", t.title, t.artist),
+        _ => " stopped".to_string()
+    };
+
+    let mut info = format!("vol {}% ", app.config.playback.volume);
+    let replay_gain = app.config.get_replay_gain();
+    if replay_gain.mode != ReplayGainMode::Off {
+        // gains are only read from id3 frames, other songs need `:analyze`.
+        let gain = match app.get_playing_track().map(|t| replay_gain.get_gain(t)) {
+            Some(Some(g)) => format!("{:+.1}dB", g),
+            Some(None) => "no ReplayGain tags".to_string(),
+            None => "-".to_string()
+        };
+        info = format!("rg {} {}  {}", replay_gain.mode.get_title(), gain, info);
+    }
+    let tempo = app.config.get_tempo();
+    if !tempo.is_default() {
+        let pitch = if tempo.pitch != 0.0 { format!(" {:+}st", tempo.pitch) } else { String::new() };
+        info = format!("{}x{}  {}", tempo.speed, pitch, info);
+    }
+    if app.equalizer.get().is_active() {
+        info = format!("eq {}  {}", app.eq_preset.as_ref().map(String::as_str).unwrap_or("custom"), info);
+    }
+    if !app.queue.items.is_empty() {
+        info = format!("{} queued  {}", app.queue.items.len(), info);
+    }
+    if let Some(count) = app.pending_count {
+        info = format!("{}  {}", count, info);
+    }
+    if let Some(list) = app.get_current_list() {
+        let marked = list.get_marked().len();
+        if list.is_visual() {
+            info = format!("-- VISUAL -- {}  {}", marked, info);
+        } else if marked > 0 {
+            info = format!("{} marked  {}", marked, info);
+        }
+    }
+    if let Some(t) = app.get_playing_track() {
+        if t.duration > 0 {
+            info = format!("{}  {}", format_duration(t.duration / 1000), info);
+        }
+    }
+
+    let info_width = get_width(&info);
+    let state = truncate(&state, (area.width as usize).saturating_sub(info_width + 1));
+    let padding = (area.width as usize).saturating_sub(get_width(&state) + info_width);
+    let line = format!("{}{}{}", state, " ".repeat(padding), info);
+
+    Paragraph::new([Text::raw(line)].iter())
+        .style(app.theme.status_bar)
+        .render(f, area);
+}
+
+fn draw_help<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend
+{
+    let area = get_centered_rect(70, 80, area);
+
+    let mut lines = Vec::new();
+    for context in CONTEXTS.iter() {
+        lines.push(Text::styled(format!("{}\n", context.get_title()), app.theme.header));
+        for binding in get_bindings(*context) {
+            lines.push(Text::styled(
+                format!("  {:<16} {}\n", format_keys(binding.keys), binding.description),
+                app.theme.text
+            ));
+        }
+        lines.push(Text::raw("\n"));
+    }
+
+    lines.push(Text::styled("commands\n", app.theme.header));
+    for (_, usage, description) in COMMANDS.iter() {
+        lines.push(Text::styled(format!("  {:<16} {}\n", usage, description), app.theme.text));
+    }
+
+    Clear.render(f, area);
+    Paragraph::new(lines.iter())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.theme.active)
+                .title_style(app.theme.active)
+                .title("Help (j/k: scroll, any other key: close)")
+        )
+        .scroll(app.help_scroll)
+        .render(f, area);
+}
+
+fn draw_message_log<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend
+{
+    let area = get_centered_rect(70, 80, area);
+
+    let mut lines = Vec::new();
+    if app.messages.is_empty() {
+        lines.push(Text::styled("  no messages yet\n", app.theme.text));
+    }
+    for message in app.messages.iter() {
+        lines.push(Text::styled(format!(" {:>7} ", format_age(message.time)), app.theme.text));
+        lines.push(Text::styled(format!(" {:<7} ", message.level.get_title()), get_level_style(app, message.level)));
+        lines.push(Text::styled(format!(" {}\n", message.text), app.theme.text));
+    }
+
+    Clear.render(f, area);
+    Paragraph::new(lines.iter())
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.theme.active)
+                .title_style(app.theme.active)
+                .title(&format!("Messages ({}, newest first, j/k: scroll, any other key: close)", app.messages.len()))
+        )
+        .wrap(true)
+        .scroll(app.log_scroll)
+        .render(f, area);
+}
+
+fn draw_output_picker<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend
+{
+    let area = get_centered_rect(50, 50, area);
+    let items: Vec<String> = app.output_choices
+        .iter()
+        .map(|o| format!("{} {}", if *o == app.output { "*" } else { " " }, get_output_label(o)))
+        .collect();
+
+    Clear.render(f, area);
+    SelectableList::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.theme.active)
+                .title_style(app.theme.active)
+                .title("Output (enter: play on it, any other key: close)")
         )
+        .items(&items)
+        .select(Some(app.output_choice))
+        .style(app.theme.text)
+        .highlight_style(app.theme.selection)
+        .highlight_symbol(">")
+        .render(f, area);
+}
+
+/// a row per band, the gain drawn as a bar growing from the middle.
+fn draw_equalizer<B>(f: &mut Frame<B>, app: &App, area: Rect)
+    where B: Backend
+{
+    let area = get_centered_rect(60, 60, area);
+    let settings = app.equalizer.get();
+    // cells on each side of the middle.
+    let half = (area.width as usize).saturating_sub(22) / 2;
+    let items: Vec<String> = (0..BANDS.len())
+        .map(|band| {
+            let gain = settings.gains[band];
+            let cells = (gain.abs() / MAX_GAIN * half as f32).round() as usize;
+            let (left, right) = match gain < 0.0 {
+                true => (format!("{:>width$}", "=".repeat(cells), width = half), " ".repeat(half)),
+                false => (" ".repeat(half), format!("{:<width$}", "=".repeat(cells), width = half))
+            };
+            format!("{:>4} {}|{} {:+5.1}dB", get_band_label(band), left, right, gain)
+        })
+        .collect();
+
+    let preset = app.eq_preset.as_ref().map(String::as_str).unwrap_or("custom");
+    let state = if settings.bypass { ", bypassed" } else { "" };
+    let title = format!("Equalizer: {}{} (h/l: -/+1dB, 0: reset, [/]: preset, b: bypass, s: save)", preset, state);
+
+    Clear.render(f, area);
+    SelectableList::default()
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(app.theme.active)
+                .title_style(app.theme.active)
+                .title(&title)
+        )
+        .items(&items)
+        .select(Some(app.eq_band))
+        .style(app.theme.text)
+        .highlight_style(app.theme.selection)
+        .highlight_symbol(">")
+        .render(f, area);
+}
+
+fn get_level_style(app: &App, level: Level) -> Style {
+    match level {
+        Level::Info => app.theme.status_bar,
+        Level::Warning => app.theme.warning,
+        Level::Error => app.theme.error,
+    }
+}
+
+fn draw_directory<B>(f: &mut Frame<B>, app: &App, area: Rect, title: &str)
+    where B: Backend 
+{
+    let active = get_color(app, *&app.tabs.panels.index == 0);
+
+    let mut d = Vec::new();
+    let matched = if app.tabs.panels.index == 0 { app.get_filter_matches() } else { vec![] };
+
+    let offset = app.directory.offset;
+    let end = (offset + get_list_height(area)).min(app.directory.items.len());
+    for (i, directory) in app.directory.items[offset.min(end)..end].iter().enumerate() {
+        let i = i + offset;
+        // list items share one style, filter matches are marked instead.
+        let marker = if matched.contains(&i) { "*" } else { "" };
+        // tree view indents entries of expanded directories and shows whether they are expanded.
+        let (indent, symbol) = match (app.is_tree_view, directory.is_dir()) {
+            (true, true) if app.expanded.contains(directory) => ("  ".repeat(app.get_directory_depth(directory)), "▾ "),
+            (true, true) => ("  ".repeat(app.get_directory_depth(directory)), "▸ "),
+            (true, false) => ("  ".repeat(app.get_directory_depth(directory)), "  "),
+            _ => (String::new(), "/")
+        };
+        // room left after borders, highlight symbol, indent, marker and symbol.
+        let width = (area.width as usize).saturating_sub(4 + indent.len() + marker.len() + get_width(symbol));
+        let name = app.get_directory_label(directory);
+        let name = if app.config.ui.marquee && i == app.directory.selected {
+            marquee(&name, width, app.ticks)
+        } else {
+            truncate(&name, width)
+        };
+        d.push(format!("{}{}{}{}", indent, marker, symbol, name))
     }
        
    Layout::default()
@@ -203,17 +620,19 @@ fn draw_directory<B>(f: &mut Frame<B>, app: &App, area: Rect)
                 .borders(Borders::ALL)
                 .border_style(active)
                 .title_style(active)
-                .title(app.tabs.panels.titles[app.tabs.index % app.tabs.titles.len() - 1])
+                .title(title)
             )
             .items(&d)
-            .select(Some(app.directory.selected))
-            .highlight_style(Style::default().fg(Color::Gray))
+            .select(app.directory.selected.checked_sub(offset))
+            .style(app.theme.text)
+            .highlight_style(app.theme.selection)
             .highlight_symbol(">")
             .render(f, area);
+    draw_position(f, app, area, &app.directory);
 }
 
 
-fn draw_directory_files<B>(f: &mut Frame<B>, app: &App, area: Rect)
+fn draw_directory_files<B>(f: &mut Frame<B>, app: &App, area: Rect, title: &str)
     where B: Backend 
 {
 
@@ -222,37 +641,30 @@ fn draw_directory_files<B>(f: &mut Frame<B>, app: &App, area: Rect)
 
     if app.is_track_valid {
          is_track_highlighted = true;
-         items = app
-              .directory_files
-              .items
-              .iter()
-              .map(|item| TableItem {
-                  id: item.title.to_string(),
-                  format: vec![
-                      item.title.to_string().to_owned(),
-                      item.artist.to_string().to_owned(),
-                      item.album.to_string().to_owned(),
-                  ],
-              })
-              .collect::<Vec<TableItem>>();
+         items = get_visible_track_items(app, &app.directory_files, area);
     } else {
         is_track_highlighted = false;
-        items.push(get_init_selection_table_state("error"));
+        items.push(get_init_selection_table_state("No song found.."));
     };
 
 
-    let header = get_header(&area);
+    let header = get_header(app, &area);
 
     draw_table(
         f,
         app,
         area,
-        (&app.tabs.panels.titles[1], &header),
-        &items,
+        (title, &header),
+        (&items, if is_track_highlighted { app.directory_files.offset } else { 0 }),
+        &if app.tabs.panels.index == 1 { app.get_filter_matches() } else { vec![] },
+        &app.directory_files.get_marked(),
         true,
         *&app.tabs.panels.index == 1,
         is_track_highlighted
     );
+    if is_track_highlighted {
+        draw_position(f, app, area, &app.directory_files);
+    }
 }
 
 
@@ -261,21 +673,50 @@ fn draw_table<B>(
     app: &App,
     area: Rect,
     table_layout: (&str, &[TableHeader]), 
-    items: &[TableItem],     
+    (items, offset): (&[TableItem], usize),
+    matched: &[usize],
+    marked: &[usize],
     should_select: bool,
     highlight_state: bool,
     should_active: bool
 ) where
     B: Backend,
 {
+    let (title, header_columns) = table_layout;
+    let widths = header_columns.iter().map(|h| h.width).collect::<Vec<u16>>();
+
+    let select: Option<usize>;
+    if should_select {
+        select = app.get_playing_track_index();
+    } else {
+       select = None;
+    };
+
+    // items are the visible rows only, starting at row `offset`.
     let rows = items.iter().enumerate().map(|(i, item)| {
-        let formatted_row = item.format.clone();
-        let mut style = Style::default().fg(Color::White); // default styling
+        let i = i + offset;
+        let mut formatted_row = item.format.clone();
+        if app.config.ui.marquee && select == Some(i) {
+            formatted_row = formatted_row
+                .iter()
+                .zip(widths.iter())
+                .map(|(field, w)| marquee(field, (*w as usize).saturating_sub(3), app.ticks))
+                .collect();
+        }
+        let mut style = app.theme.text; // default styling
+
+        if matched.contains(&i) {
+            style = app.theme.matched;
+        }
+
+        if marked.contains(&i) {
+            style = app.theme.marked;
+        }
      
         // TODO: highlight from widget instead?
         if should_active {
-            match app.playing_track_index {
-                Some(x) => if i == x { style = Style::default().fg(Color::Green);},
+            match app.get_playing_playlist_index() {
+                Some(x) => if i == x { style = app.theme.playing;},
                 None => {}
             }
         }
@@ -283,34 +724,24 @@ fn draw_table<B>(
         PlaylistRow::StyledData(formatted_row.into_iter(), style)
     });
 
-    
-    let (title, header_columns) = table_layout;
     let select_active: Style;
 
-    let widths = header_columns.iter().map(|h| h.width).collect::<Vec<u16>>();
-
     let symbol = get_symbol(app.is_playlist_added);
-    select_active = get_select_active_color(app.is_playlist_added);
-
-
-    let select: Option<usize>;
-    if should_select {
-        select = app.get_playing_track_index();
-    } else {
-       select = None;
-    };
+    select_active = get_select_active_color(app, app.is_playlist_added);
 
-    PlaylistTable::new(header_columns.iter().map(|h| h.text), rows)
+    PlaylistTable::new(header_columns.iter().map(|h| h.text.as_str()), rows)
         .block(
             Block::default()
                 .borders(Borders::ALL)
-                .style(Style::default().fg(Color::White))
+                .style(app.theme.text)
                 .title(title)
-                .title_style(get_color(highlight_state))
-                .border_style(get_color(highlight_state)),
+                .title_style(get_color(app, highlight_state))
+                .border_style(get_color(app, highlight_state)),
         )
-        .style(Style::default().fg(Color::White))
+        .style(app.theme.text)
+        .header_style(app.theme.header)
         .widths(&widths)
+        .offset(offset)
         .select( select )
         .set_select_active_style(select_active)
         .select_symbol(symbol)
@@ -327,15 +758,15 @@ fn draw_table<B>(
 // `percentage` param needs to be between 0 and 1
 fn get_percentage_width(width: u16, percentage: f32) -> u16 {
     let padding = 3;
-    let width = width - padding;
+    let width = width.saturating_sub(padding);
     (f32::from(width) * percentage) as u16
 }
 
 
-fn get_color(is_active : bool) -> Style {
+fn get_color(app: &App, is_active : bool) -> Style {
     match is_active {
-        true => Style::default().fg(Color::Green),
-        _ => Style::default().fg(Color::White),
+        true => app.theme.active,
+        _ => app.theme.border,
     }
 }
 
@@ -347,33 +778,114 @@ fn get_symbol(state: bool) -> String {
 }
 
 
-fn get_select_active_color(state: bool) -> Style {
+fn get_select_active_color(app: &App, state: bool) -> Style {
     match state {
-        true => {Style::default().fg(Color::Green)},
-        _ => {Style::default().fg(Color::Gray)}
+        true => {app.theme.active},
+        _ => {app.theme.selection}
     }
         
 }
 
-fn get_header(area: &Rect) -> [TableHeader; 3]{
+fn get_column_title(column: &str) -> &str {
+    match column {
+        "title" => "Title",
+        "artist" => "Artist",
+        "album" => "Album",
+        "year" => "Year",
+        "track" => "#",
+        "duration" => "Time",
+        _ => ""
+    }
+}
+
+/// `dir` relative to its music root, e.g. `music › rock › pixies`,
+/// cut from the start when longer than `width`.
+fn get_breadcrumb(app: &App, dir: Option<&Path>, width: usize) -> String {
+    let dir = match dir {
+        Some(d) if d.as_os_str().is_empty() => return "library".to_string(),
+        Some(d) => d,
+        None => return String::new()
+    };
+    let (root, relative) = match app.config.get_root_of(dir) {
+        Some(r) => (r.as_path(), dir.strip_prefix(r).unwrap_or(dir)),
+        None => (Path::new(""), dir)
+    };
 
-  let header = [
-        TableHeader {
-            text: "  Title",
-            width: get_percentage_width(area.width, 0.33),
-        },
-        TableHeader {
-            text: "Artist",
-            width: get_percentage_width(area.width, 0.33),
-        },
-        TableHeader {
-            text: "Album",
-            width: get_percentage_width(area.width, 0.33),
-        },
-];
+    let mut parts = vec![];
+    if let Some(name) = root.file_name() {
+        parts.push(name.to_string_lossy().to_string());
+    }
+    parts.extend(relative.iter().map(|c| c.to_string_lossy().to_string()).filter(|c| c != "/"));
+    truncate_start(&parts.join(" › "), width)
+}
+
+/// configured columns fitting in `area`, less important ones are hidden first.
+fn get_columns<'c>(app: &'c App, area: &Rect) -> Vec<&'c str> {
+    let columns = &app.config.ui.columns;
+    let fit = (area.width.saturating_sub(2) / MIN_COLUMN_WIDTH).max(1) as usize;
+    if columns.len() <= fit {
+        return columns.iter().map(String::as_str).collect();
+    }
+
+    let mut by_priority: Vec<&str> = columns.iter().map(String::as_str).collect();
+    by_priority.sort_by_key(|c| COLUMN_PRIORITY.iter().position(|p| p == c).unwrap_or(COLUMN_PRIORITY.len()));
+    by_priority.truncate(fit);
+    columns.iter().map(String::as_str).filter(|c| by_priority.contains(c)).collect()
+}
 
-  header
+fn get_header(app: &App, area: &Rect) -> Vec<TableHeader>{
+
+  let columns = get_columns(app, area);
+  let percentage = 1.0 / columns.len() as f32;
+
+  columns
+      .iter()
+      .enumerate()
+      .map(|(i, c)| TableHeader {
+          // first column leaves room for select symbol.
+          text: if i == 0 { format!("  {}", get_column_title(c)) } else { get_column_title(c).to_string() },
+          width: get_percentage_width(area.width, percentage),
+      })
+      .collect()
+
+}
+
+/// rows of a table drawn in `area`: area without borders, header and the gap below it.
+pub fn get_table_height(area: Rect) -> usize {
+    area.height.saturating_sub(4) as usize
+}
+
+/// rows of a list drawn in `area`: area without borders.
+pub fn get_list_height(area: Rect) -> usize {
+    area.height.saturating_sub(2) as usize
+}
+
+/// only rows inside the viewport are formatted, so huge lists draw fast.
+fn get_visible_track_items(app: &App, list: &ListState<Track>, area: Rect) -> Vec<TableItem> {
+    let end = (list.offset + get_table_height(area)).min(list.items.len());
+    get_track_items(&list.items[list.offset.min(end)..end], &get_columns(app, &area))
+}
+
+fn get_track_items(tracks: &[Track], columns: &[&str]) -> Vec<TableItem> {
+    tracks
+        .iter()
+        .map(|item| TableItem {
+            id: item.title.to_string(),
+            format: columns.iter().map(|c| item.get_column(c)).collect(),
+        })
+        .collect::<Vec<TableItem>>()
+}
 
+// `percent_x` and `percent_y` are between 0 and 100.
+fn get_centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let width = area.width * percent_x / 100;
+    let height = area.height * percent_y / 100;
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height
+    )
 }
 
 fn get_init_selection_table_state(placeholder: &str) -> TableItem{